@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use anyhow::Context;
+use anyhow::Result;
+use anyhow::anyhow;
+use serde::Deserialize;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tracing::info;
+
+use crate::config::CommandConfig;
+use crate::console;
+use crate::process::table;
+use crate::template;
+
+/// Things a `[[cmd]]` entry can do without running a program
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+  /// Waits for a key press, or `seconds` if set
+  Pause,
+  /// Copies the file or folder at `path` to `to`
+  Copy,
+  /// Deletes the file or folder at `path`
+  Delete,
+  /// Kills every process called `name`
+  KillProcess,
+  /// Opens the runner's log file
+  OpenLog,
+  /// Writes `contents` to the file at `path`
+  WriteFile,
+}
+
+impl fmt::Display for Action {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Action::Pause => write!(f, "pause"),
+      Action::Copy => write!(f, "copy"),
+      Action::Delete => write!(f, "delete"),
+      Action::KillProcess => write!(f, "kill_process"),
+      Action::OpenLog => write!(f, "open_log"),
+      Action::WriteFile => write!(f, "write_file"),
+    }
+  }
+}
+
+/// Runs the action of `cmd`. Placeholders in its settings are filled from
+/// `vars`.
+pub async fn run(
+  action: Action,
+  cmd: &CommandConfig,
+  vars: &HashMap<String, String>,
+) -> Result<()> {
+  let render = |value: &str| template::render(value, vars);
+  let required = |value: &Option<String>, name: &str| {
+    value
+      .as_deref()
+      .map(render)
+      .ok_or_else(|| anyhow!("{} needs `{}`", action, name))
+  };
+  let path = || {
+    Some(&cmd.path)
+      .filter(|p| !p.is_empty())
+      .map(|p| PathBuf::from(render(p)))
+      .ok_or_else(|| anyhow!("{} needs `path`", action))
+  };
+
+  match action {
+    Action::Pause => pause(cmd.seconds.map(Duration::from_secs)).await,
+    Action::Copy => copy(&path()?, Path::new(&required(&cmd.to, "to")?)).await,
+    Action::Delete => delete(&path()?).await,
+    Action::KillProcess => kill_process(&required(&cmd.name, "name")?),
+    Action::OpenLog => open(&crate::log_path()),
+    Action::WriteFile => {
+      write_file(&path()?, &required(&cmd.contents, "contents")?, cmd.append).await
+    },
+  }
+}
+
+async fn pause(timeout: Option<Duration>) -> Result<()> {
+  match timeout {
+    Some(timeout) => {
+      println!(
+        "Continuing in {} seconds, or press any key...",
+        timeout.as_secs()
+      )
+    },
+    None => println!("Press any key to continue..."),
+  }
+
+  // The wait is dropped when the hook times out or the run is interrupted,
+  // and must stop reading the console then
+  let cancelled = Arc::new(AtomicBool::new(false));
+  let _cancel = CancelOnDrop(cancelled.clone());
+  tokio::task::spawn_blocking(move || console::wait_for_key(timeout, &cancelled)).await?;
+  Ok(())
+}
+
+/// Sets the flag when dropped
+struct CancelOnDrop(Arc<AtomicBool>);
+
+impl Drop for CancelOnDrop {
+  fn drop(&mut self) {
+    self.0.store(true, Ordering::SeqCst);
+  }
+}
+
+async fn copy(from: &Path, to: &Path) -> Result<()> {
+  info!(from = %from.display(), to = %to.display(), "Copying");
+
+  if fs::metadata(from).await?.is_dir() {
+    copy_dir(from.to_path_buf(), to.to_path_buf()).await
+  } else {
+    // Copying a file into a folder keeps its name
+    let to = match from.file_name() {
+      Some(name) if fs::metadata(to).await.is_ok_and(|m| m.is_dir()) => to.join(name),
+      _ => to.to_path_buf(),
+    };
+    if let Some(parent) = to.parent() {
+      fs::create_dir_all(parent).await?;
+    }
+    fs::copy(from, &to)
+      .await
+      .with_context(|| format!("Failed to copy {:?} to {:?}", from, to))?;
+    Ok(())
+  }
+}
+
+async fn copy_dir(from: PathBuf, to: PathBuf) -> Result<()> {
+  let mut dirs = vec![(from, to)];
+
+  while let Some((from, to)) = dirs.pop() {
+    fs::create_dir_all(&to).await?;
+    let mut entries = fs::read_dir(&from).await?;
+    while let Some(entry) = entries.next_entry().await? {
+      let target = to.join(entry.file_name());
+      if entry.file_type().await?.is_dir() {
+        dirs.push((entry.path(), target));
+      } else {
+        fs::copy(entry.path(), &target)
+          .await
+          .with_context(|| format!("Failed to copy {:?} to {:?}", entry.path(), target))?;
+      }
+    }
+  }
+
+  Ok(())
+}
+
+async fn delete(path: &Path) -> Result<()> {
+  let metadata = match fs::symlink_metadata(path).await {
+    Ok(metadata) => metadata,
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+      info!(path = %path.display(), "Nothing to delete");
+      return Ok(());
+    },
+    Err(e) => return Err(e.into()),
+  };
+
+  info!(path = %path.display(), "Deleting");
+  if metadata.is_dir() {
+    fs::remove_dir_all(path).await?;
+  } else {
+    fs::remove_file(path).await?;
+  }
+  Ok(())
+}
+
+async fn write_file(path: &Path, contents: &str, append: bool) -> Result<()> {
+  info!(path = %path.display(), append, "Writing file");
+
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent).await?;
+  }
+  let mut file = fs::OpenOptions::new()
+    .create(true)
+    .write(true)
+    .append(append)
+    .truncate(!append)
+    .open(path)
+    .await?;
+  file.write_all(contents.as_bytes()).await?;
+  Ok(())
+}
+
+fn kill_process(name: &str) -> Result<()> {
+  let pids = table::find(name)?;
+  if pids.is_empty() {
+    info!(name, "No process to kill");
+  }
+
+  table::kill_all(&pids)
+}
+
+/// Opens `path` with the program the desktop associates with it
+#[cfg(windows)]
+fn open(path: &Path) -> Result<()> {
+  use windows::Win32::UI::Shell::ShellExecuteW;
+  use windows::Win32::UI::WindowsAndMessaging::SW_SHOWNORMAL;
+  use windows::core::HSTRING;
+  use windows::core::PCWSTR;
+  use windows::core::w;
+
+  info!(path = %path.display(), "Opening");
+  let file = HSTRING::from(path.as_os_str());
+  let result = unsafe {
+    ShellExecuteW(
+      None,
+      w!("open"),
+      &file,
+      PCWSTR::null(),
+      PCWSTR::null(),
+      SW_SHOWNORMAL,
+    )
+  };
+
+  // Values above 32 mean success
+  if result.0 as usize <= 32 {
+    return Err(anyhow!("Failed to open {:?}", path));
+  }
+  Ok(())
+}
+
+/// Opens `path` with the program the desktop associates with it
+#[cfg(unix)]
+fn open(path: &Path) -> Result<()> {
+  let opener = if cfg!(target_os = "macos") {
+    "open"
+  } else {
+    "xdg-open"
+  };
+
+  info!(path = %path.display(), opener, "Opening");
+  std::process::Command::new(opener)
+    .arg(path)
+    .stdin(std::process::Stdio::null())
+    .stdout(std::process::Stdio::null())
+    .stderr(std::process::Stdio::null())
+    .spawn()
+    .with_context(|| format!("Failed to run {}", opener))?;
+  Ok(())
+}
@@ -1,67 +1,326 @@
-use std::{
-  env, fs,
-  path::{Path, PathBuf},
-};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
 
-use anyhow::{Result, anyhow};
+use anyhow::Context;
+use anyhow::Result;
+use anyhow::anyhow;
 use csv::ReaderBuilder;
-use serde::Deserialize;
-use tokio::{fs::File, io::AsyncReadExt};
+use csv::StringRecord;
+use tokio::fs::File;
+use tokio::io::AsyncReadExt;
+use tracing::info;
+use tracing::warn;
 
-#[derive(Debug, Deserialize)]
+/// The type of a `.build.info` column, from the `Name!TYPE:size` header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+  String,
+  Dec,
+  Hex,
+}
+
+/// A `.build.info` column header such as `Build Key!HEX:16`
+#[derive(Debug, Clone)]
+pub struct Column {
+  pub name: String,
+  pub kind: ColumnType,
+  /// Size in bytes. `0` means variable length.
+  pub size: usize,
+}
+
+impl Column {
+  /// Parses a column header. A missing or unknown type is read as `STRING`
+  /// so that columns Blizzard adds later do not make the file unreadable.
+  fn parse(header: &str) -> Self {
+    let (name, spec) = header.split_once('!').unwrap_or((header, "STRING:0"));
+    let (kind, size) = spec.split_once(':').unwrap_or((spec, "0"));
+
+    let kind = match kind.to_uppercase().as_str() {
+      "DEC" => ColumnType::Dec,
+      "HEX" => ColumnType::Hex,
+      "STRING" => ColumnType::String,
+      _ => {
+        warn!(
+          header,
+          "Unknown .build.info column type, reading it as a string"
+        );
+        ColumnType::String
+      },
+    };
+
+    Self {
+      name: name.to_string(),
+      kind,
+      size: size.parse().unwrap_or(0),
+    }
+  }
+
+  /// Checks that `value` matches the column's type
+  fn check(&self, value: &str) -> Result<()> {
+    match self.kind {
+      ColumnType::Dec if !value.is_empty() => {
+        value
+          .parse::<u64>()
+          .with_context(|| format!("invalid {} value \"{}\"", self.name, value))?;
+      },
+      ColumnType::Hex
+        if !value.chars().all(|c| c.is_ascii_hexdigit())
+          || (self.size > 0 && !value.is_empty() && value.len() != self.size * 2) =>
+      {
+        return Err(anyhow!("invalid {} value \"{}\"", self.name, value));
+      },
+      _ => {},
+    }
+
+    Ok(())
+  }
+}
+
+/// One row of `.build.info`
+#[derive(Debug, Clone, Default)]
 pub struct BuildInfoEntry {
-  #[serde(rename = "Version!STRING:0")]
+  /// Region branch, e.g. "us", "eu" or "cn"
+  pub branch: String,
+  pub active: bool,
+  pub build_key: String,
+  pub cdn_key: String,
+  pub install_key: String,
+  pub tags: Vec<String>,
+  pub armadillo: String,
+  pub last_activated: String,
   pub version: String,
-  #[serde(rename = "Product!STRING:0")]
-  pub product: crate::Product,
-}
-
-// pub fn find_wow_install_path() -> Result<PathBuf> {
-//   let key = LOCAL_MACHINE
-//     .open("SOFTWARE\\WOW6432Node\\Blizzard Entertainment\\World of Warcraft")
-//     .context("Failed to open WoW registry key. WoW may not be installed.")?;
-
-//   let value = key
-//     .get_string("InstallPath")
-//     .context("Failed to get 'InstallPath' value. The registry entry may be incomplete.")?;
-
-//   let p = PathBuf::from(value);
-//   if !p.exists() {
-//     return Err(anyhow::anyhow!(
-//       "WoW installation path does not exist: {:?}",
-//       p
-//     ));
-//   }
-
-//   Ok(p.parent().unwrap().to_owned())
-// }
-
-pub fn find_wow_install_path(product: crate::Product) -> Result<PathBuf> {
-  let pd = match env::var("PROGRAMDATA") {
-    Ok(path) => path,
-    Err(e) => {
-      return Err(anyhow!(
-        "Could not read PROGRAMDATA environment variable: {}",
-        e
-      ));
-    },
-  };
-
-  let p = PathBuf::from(pd)
-    .join("Battle.net")
-    .join("Agent")
-    .join("product.db");
-  let data = fs::read(&p)?;
-  let pdb = crate::productdb::deserialize(&data)?;
-  for install in pdb.product_installs {
-    if install.product_code == product.to_string() {
-      if let Some(settings) = install.settings {
-        return Ok(PathBuf::from(settings.install_path));
+  pub product: String,
+}
+
+impl BuildInfoEntry {
+  /// Reads a row. Cells that do not match their column's type are skipped
+  /// with a warning instead of failing the whole file.
+  fn from_record(columns: &[Column], record: &StringRecord) -> Self {
+    let mut entry = Self::default();
+
+    for (column, value) in columns.iter().zip(record.iter()) {
+      let value = value.trim();
+      if let Err(e) = column.check(value) {
+        warn!(error = %e, "Skipped malformed .build.info value");
+        continue;
+      }
+
+      match column.name.as_str() {
+        "Branch" => entry.branch = value.to_string(),
+        "Active" => entry.active = value == "1",
+        "Build Key" => entry.build_key = value.to_lowercase(),
+        "CDN Key" => entry.cdn_key = value.to_lowercase(),
+        "Install Key" => entry.install_key = value.to_lowercase(),
+        "Tags" => {
+          for tag in value.split([':', ' ']).filter(|t| !t.is_empty()) {
+            if !entry.tags.iter().any(|t| t == tag) {
+              entry.tags.push(tag.to_string());
+            }
+          }
+        },
+        "Armadillo" => entry.armadillo = value.to_string(),
+        "Last Activated" => entry.last_activated = value.to_string(),
+        "Version" => entry.version = value.to_string(),
+        "Product" => entry.product = value.to_string(),
+        _ => {},
       }
     }
+
+    entry
+  }
+
+  /// Whether this entry's branch belongs to `region`
+  pub fn is_region(&self, region: crate::Region) -> bool {
+    match region {
+      crate::Region::China => self.branch.eq_ignore_ascii_case("cn"),
+      crate::Region::Global => !self.branch.eq_ignore_ascii_case("cn"),
+      crate::Region::Auto => true,
+    }
+  }
+}
+
+/// Picks the entry for `product` in `region`, preferring active entries and
+/// falling back to any entry for the product
+pub fn select_entry(
+  entries: Vec<BuildInfoEntry>,
+  product: &str,
+  region: crate::Region,
+) -> Option<BuildInfoEntry> {
+  entries
+    .into_iter()
+    .filter(|e| e.product == product)
+    .max_by_key(|e| {
+      (
+        e.active && e.is_region(region),
+        e.active,
+        e.is_region(region),
+      )
+    })
+}
+
+/// Folder name of a WoW install under a drive or `Program Files`
+const WOW_FOLDER: &str = "World of Warcraft";
+
+/// A place to look for the WoW install, yielding candidate install paths
+type DiscoveryStep<'a> = (&'static str, Box<dyn Fn() -> Result<Vec<PathBuf>> + 'a>);
+
+/// Finds the WoW install folder for `product`. A configured path is used
+/// as is and must be a valid install. Otherwise the Battle.net product
+/// database, the registry, common install locations and Wine/Proton prefixes
+/// are tried in order, and the first candidate whose `.build.info` lists the
+/// product wins.
+pub fn find_wow_install_path(
+  product: &crate::product::ProductInfo,
+  configured: Option<&Path>,
+  db: Option<&crate::productdb::Database>,
+) -> Result<PathBuf> {
+  if let Some(path) = configured {
+    info!(path = %path.display(), "Using configured WoW install path");
+    check_install_path(path, product)
+      .with_context(|| format!("wow_install_path {} is not usable", path.display()))?;
+    return Ok(path.to_path_buf());
+  }
+
+  let steps: Vec<DiscoveryStep> = vec![
+    (
+      "product.db",
+      Box::new(|| product_db_candidates(product, db)),
+    ),
+    ("registry", Box::new(registry_candidates)),
+    ("default locations", Box::new(|| Ok(default_candidates()))),
+    ("wine prefixes", Box::new(|| Ok(wine_candidates()))),
+  ];
+
+  for (step, candidates) in steps {
+    let candidates = match candidates() {
+      Ok(candidates) if candidates.is_empty() => {
+        info!(step, "No WoW install candidates");
+        continue;
+      },
+      Ok(candidates) => candidates,
+      Err(e) => {
+        info!(step, reason = %e, "Skipped WoW install discovery step");
+        continue;
+      },
+    };
+
+    for path in candidates {
+      info!(step, path = %path.display(), "Trying WoW install path");
+      match check_install_path(&path, product) {
+        Ok(()) => {
+          info!(step, path = %path.display(), "Found WoW install path");
+          return Ok(path);
+        },
+        Err(e) => info!(step, path = %path.display(), reason = %e, "Rejected WoW install path"),
+      }
+    }
+  }
+
+  Err(anyhow!("could not find {} install path", product.code))
+}
+
+/// Checks that `path` holds a `.build.info` listing `product`
+fn check_install_path(path: &Path, product: &crate::product::ProductInfo) -> Result<()> {
+  let buildinfo_path = path.join(".build.info");
+  let contents = fs::read(&buildinfo_path)
+    .with_context(|| format!("could not read {}", buildinfo_path.display()))?;
+
+  if !parse(&contents)?
+    .iter()
+    .any(|e| e.product == product.build_info_product)
+  {
+    return Err(anyhow!(
+      ".build.info does not list product {}",
+      product.build_info_product
+    ));
   }
 
-  Err(anyhow!("could not find {} install path", product))
+  Ok(())
+}
+
+fn product_db_candidates(
+  product: &crate::product::ProductInfo,
+  db: Option<&crate::productdb::Database>,
+) -> Result<Vec<PathBuf>> {
+  let db = db.ok_or_else(|| anyhow!("product.db could not be read"))?;
+  let install = db
+    .find_install(&product.code)
+    .ok_or_else(|| anyhow!("{} is not listed in product.db", product.code))?;
+
+  Ok(
+    install
+      .settings
+      .iter()
+      .map(|settings| db.location.install_path(&settings.install_path))
+      .collect(),
+  )
+}
+
+#[cfg(windows)]
+fn registry_candidates() -> Result<Vec<PathBuf>> {
+  let key = windows_registry::LOCAL_MACHINE
+    .open("SOFTWARE\\WOW6432Node\\Blizzard Entertainment\\World of Warcraft")
+    .context("Failed to open WoW registry key")?;
+
+  let value = key
+    .get_string("InstallPath")
+    .context("Failed to get 'InstallPath' value")?;
+
+  // InstallPath points at a flavor folder such as `_retail_`
+  let p = PathBuf::from(value.trim_end_matches(['\\', '/']));
+  Ok(p.parent().into_iter().map(Path::to_path_buf).collect())
+}
+
+#[cfg(not(windows))]
+fn registry_candidates() -> Result<Vec<PathBuf>> {
+  Err(anyhow!("the registry is only available on Windows"))
+}
+
+/// Common install locations on every drive
+fn default_candidates() -> Vec<PathBuf> {
+  if !cfg!(windows) {
+    return vec![];
+  }
+
+  let mut candidates: Vec<PathBuf> = ["ProgramFiles(x86)", "ProgramFiles"]
+    .iter()
+    .filter_map(env::var_os)
+    .map(|dir| PathBuf::from(dir).join(WOW_FOLDER))
+    .collect();
+
+  for drive in 'C'..='Z' {
+    let root = PathBuf::from(format!("{}:\\", drive));
+    if !root.exists() {
+      continue;
+    }
+
+    for dir in ["", "Games", "Program Files (x86)", "Program Files"] {
+      let candidate = root.join(dir).join(WOW_FOLDER);
+      if !candidates.contains(&candidate) {
+        candidates.push(candidate);
+      }
+    }
+  }
+
+  candidates.retain(|p| p.exists());
+  candidates
+}
+
+/// The default install locations inside each Wine/Proton prefix
+fn wine_candidates() -> Vec<PathBuf> {
+  crate::wine::discover_prefixes()
+    .into_iter()
+    .flat_map(|prefix| {
+      let drive_c = prefix.join("drive_c");
+      [
+        drive_c.join("Program Files (x86)").join(WOW_FOLDER),
+        drive_c.join("Program Files").join(WOW_FOLDER),
+      ]
+    })
+    .filter(|p| p.exists())
+    .collect()
 }
 
 pub async fn get_build_infos<P: AsRef<Path>>(path: P) -> Result<Vec<BuildInfoEntry>> {
@@ -72,16 +331,90 @@ pub async fn get_build_infos<P: AsRef<Path>>(path: P) -> Result<Vec<BuildInfoEnt
   let mut contents = Vec::new();
   file.read_to_end(&mut contents).await?;
 
+  parse(&contents)
+}
+
+/// Parses the contents of a `.build.info` file
+pub fn parse(contents: &[u8]) -> Result<Vec<BuildInfoEntry>> {
   // Create a new CSV reader builder, reading from the in-memory buffer.
   let mut rdr = ReaderBuilder::new()
     .delimiter(b'|')
     .has_headers(true)
-    .from_reader(contents.as_slice());
+    .flexible(true)
+    .from_reader(contents);
+
+  let columns: Vec<Column> = rdr.headers()?.iter().map(Column::parse).collect();
+
+  rdr
+    .records()
+    .map(|record| Ok(BuildInfoEntry::from_record(&columns, &record?)))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::Region;
+
+  /// A retail and classic install, as written by Battle.net
+  const SAMPLE: &str = "\
+Branch!STRING:0|Active!DEC:1|Build Key!HEX:16|CDN Key!HEX:16|Install Key!HEX:16|IM Size!DEC:4|CDN Path!STRING:0|CDN Hosts!STRING:0|CDN Servers!STRING:0|Tags!STRING:0|Armadillo!STRING:0|Last Activated!STRING:0|Version!STRING:0|KeyRing!HEX:16|Product!STRING:0
+us|1|A8C3C5CA0C4F4B1C7B0E2D2C9F2F5F6A|0AB3F1E8E6A0F1D2B6E0C9D8E7F6A5B4|B6E7F8A9C0D1E2F3A4B5C6D7E8F9A0B1||tpr/wow|us.cdn.blizzard.com level3.blizzard.com|http://us.cdn.blizzard.com/?maxhosts=4|Windows x86_64 US? acct-USA? geoip-US? enUS speech?:Windows x86_64 US? acct-USA? geoip-US? enUS text?||2024-11-20T18:02:11Z|11.0.5.57388|3CA57040B9A6E0A7A8D1B1F0C1A3F6E2|wow
+eu|0|C1D2E3F4A5B6C7D8E9F0A1B2C3D4E5F6|D1E2F3A4B5C6D7E8F9A0B1C2D3E4F5A6|E1F2A3B4C5D6E7F8A9B0C1D2E3F4A5B6||tpr/wow|eu.cdn.blizzard.com|http://eu.cdn.blizzard.com/?maxhosts=4|Windows x86_64 EU? enGB speech?||2024-11-01T10:00:00Z|11.0.5.57212|3CA57040B9A6E0A7A8D1B1F0C1A3F6E2|wow
+us|1|F1E2D3C4B5A6F7E8D9C0B1A2F3E4D5C6|A1A2A3A4A5A6A7A8A9B0B1B2B3B4B5B6|C1C2C3C4C5C6C7C8C9D0D1D2D3D4D5D6||tpr/wow|us.cdn.blizzard.com|http://us.cdn.blizzard.com/?maxhosts=4|Windows x86_64 US? enUS speech?||2024-11-19T08:30:00Z|1.15.4.57134|3CA57040B9A6E0A7A8D1B1F0C1A3F6E2|wow_classic_era
+";
+
+  #[test]
+  fn parses_sample() {
+    let entries = parse(SAMPLE.as_bytes()).unwrap();
+    assert_eq!(entries.len(), 3);
 
-  // Iterate over the deserialized records and collect them into a Vec.
-  let records = rdr
-    .deserialize()
-    .collect::<Result<Vec<BuildInfoEntry>, _>>()?;
+    let retail = &entries[0];
+    assert_eq!(retail.branch, "us");
+    assert!(retail.active);
+    assert_eq!(retail.build_key, "a8c3c5ca0c4f4b1c7b0e2d2c9f2f5f6a");
+    assert_eq!(retail.version, "11.0.5.57388");
+    assert_eq!(retail.product, "wow");
+    assert_eq!(retail.last_activated, "2024-11-20T18:02:11Z");
+    assert!(retail.tags.iter().any(|t| t == "enUS"));
+    assert_eq!(retail.tags.iter().filter(|t| *t == "enUS").count(), 1);
+  }
+
+  #[test]
+  fn selects_active_entry_for_region() {
+    let entries = parse(SAMPLE.as_bytes()).unwrap();
+
+    let retail = select_entry(entries.clone(), "wow", Region::Global).unwrap();
+    assert_eq!(retail.version, "11.0.5.57388");
+
+    let era = select_entry(entries.clone(), "wow_classic_era", Region::Global).unwrap();
+    assert_eq!(era.version, "1.15.4.57134");
+
+    // No China entry, so the active one is still preferred
+    let china = select_entry(entries.clone(), "wow", Region::China).unwrap();
+    assert_eq!(china.branch, "us");
 
-  Ok(records)
+    assert!(select_entry(entries, "wow_beta", Region::Global).is_none());
+  }
+
+  #[test]
+  fn reads_unknown_column_types_as_strings() {
+    let contents = "Branch!STRING:0|Active!DEC:1|Shiny!BLOB:8|Extra|Version!STRING:0|Product!STRING:0\n\
+                    us|1|xyz|abc|11.0.5.57388|wow\n";
+    let entries = parse(contents.as_bytes()).unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].version, "11.0.5.57388");
+    assert_eq!(entries[0].product, "wow");
+  }
+
+  #[test]
+  fn skips_malformed_cells() {
+    let contents = "Branch!STRING:0|Active!DEC:1|Build Key!HEX:16|Version!STRING:0|Product!STRING:0\n\
+                    us|yes|NOTHEX|11.0.5.57388|wow\n";
+    let entries = parse(contents.as_bytes()).unwrap();
+    assert_eq!(entries.len(), 1);
+    assert!(!entries[0].active);
+    assert!(entries[0].build_key.is_empty());
+    assert_eq!(entries[0].version, "11.0.5.57388");
+  }
 }
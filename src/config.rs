@@ -1,54 +1,275 @@
+use std::collections::HashMap;
 use std::fs;
 use std::io;
+use std::time::Duration;
 
 use anyhow::Context;
 use anyhow::Result;
 use anyhow::anyhow;
 use serde::Deserialize;
+use serde::Deserializer;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct CommandConfig {
   pub trigger: String,
+  /// Built-in action to run instead of a program
+  pub action: Option<crate::action::Action>,
+  /// The program to run, or the file for the `copy`, `delete` and
+  /// `write_file` actions
+  #[serde(default)]
   pub path: String,
   #[serde(default)]
   pub args: Vec<String>,
+  /// How long `pause` waits. Waits for a key press when not set.
+  pub seconds: Option<u64>,
+  /// Where `copy` copies to
+  pub to: Option<String>,
+  /// The process `kill_process` kills
+  pub name: Option<String>,
+  /// What `write_file` writes
+  pub contents: Option<String>,
+  /// Makes `write_file` append instead of replacing the file
+  #[serde(default)]
+  pub append: bool,
+  /// How the program's output is connected
+  #[serde(default)]
+  pub mode: HookMode,
+  /// Waits for the program to exit before the run continues
+  #[serde(default = "default_true")]
+  pub wait_for_exit: bool,
+  /// Working directory. Defaults to the runner's.
+  pub cwd: Option<String>,
+  /// Kills the command if it runs longer than this
+  pub timeout_secs: Option<u64>,
+  /// For `before_download` and `before_launch`: the step is aborted when the
+  /// command fails or exits with a non-zero code
+  #[serde(default)]
+  pub required: bool,
+  /// Only runs the command for these products, regions and features
+  #[serde(default)]
+  pub when: When,
+}
+
+impl CommandConfig {
+  pub fn timeout(&self) -> Option<Duration> {
+    self.timeout_secs.map(Duration::from_secs)
+  }
+}
+
+/// How a `[[cmd]]` program is run
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookMode {
+  /// In a pseudo terminal, with its output logged
+  #[default]
+  Pty,
+  /// With pipes, with its output logged
+  Pipe,
+  /// In the runner's console. No output is logged.
+  Inherit,
+  /// On its own, without the runner's console. It is not waited for and
+  /// keeps running after the runner exits.
+  Detached,
+}
+
+impl HookMode {
+  /// The mode for `process::run`, or `None` for detached programs
+  pub fn process_mode(self) -> Option<crate::process::Mode> {
+    match self {
+      HookMode::Pty => Some(crate::process::Mode::Pty),
+      HookMode::Pipe => Some(crate::process::Mode::Pipe),
+      HookMode::Inherit => Some(crate::process::Mode::Inherit),
+      HookMode::Detached => None,
+    }
+  }
+}
+
+fn default_true() -> bool {
+  true
+}
+
+/// Conditions on the run's context. An empty list matches anything; any
+/// other list must contain the context's value.
+#[derive(Debug, Default, Deserialize, Clone)]
+#[serde(default)]
+pub struct When {
+  #[serde(deserialize_with = "one_or_many")]
+  pub product: Vec<String>,
+  #[serde(deserialize_with = "one_or_many")]
+  pub region: Vec<String>,
+  #[serde(deserialize_with = "one_or_many")]
+  pub feature: Vec<String>,
+}
+
+impl When {
+  pub fn matches(&self, vars: &HashMap<String, String>) -> bool {
+    [
+      ("product", &self.product),
+      ("region", &self.region),
+      ("feature", &self.feature),
+    ]
+    .into_iter()
+    .all(|(name, allowed)| {
+      allowed.is_empty()
+        || vars
+          .get(name)
+          .is_some_and(|value| allowed.iter().any(|a| a.eq_ignore_ascii_case(value)))
+    })
+  }
 }
 
 #[derive(Debug, Deserialize)]
 #[serde(default)]
 pub struct Config {
   pub region: crate::Region,
-  pub product: crate::Product,
-  pub feature: crate::Feature,
+  /// Battle.net product code, or "auto"
+  pub product: String,
+  /// Features in order of preference. Accepts a single value or a list.
+  #[serde(deserialize_with = "one_or_many")]
+  pub feature: Vec<crate::Feature>,
   pub output_directory: String,
   pub mega_folder: String,
+  /// Skips install discovery when set
+  pub wow_install_path: Option<String>,
   #[serde(default)]
   pub cmd: Vec<CommandConfig>,
+  /// Patterns that turn lines of iMorph's output into events
+  #[serde(default)]
+  pub output_rule: Vec<crate::output_rule::OutputRuleConfig>,
+  /// How iMorph is started
+  pub launch: crate::launch::LaunchConfig,
+  /// Starting WoW along with iMorph
+  pub launch_wow: crate::launch::LaunchWowConfig,
+  /// Waiting for Battle.net to finish updating WoW
+  pub wait_for_update: crate::productdb::WaitForUpdateConfig,
+  /// Restarting iMorph after it exits unexpectedly
+  pub supervise: crate::supervise::SuperviseConfig,
+  /// Which WoW version wins when `.build.info` and `product.db` disagree
+  pub version_source: crate::productdb::VersionSource,
+  /// Downloads iMorph ahead of WoW patches Battle.net downloads in the
+  /// background
+  pub prefetch: bool,
+  /// How often pre-fetching checks for a new patch or iMorph release
+  pub prefetch_poll_secs: u64,
+  /// Extra products, or replacements for the built-in ones
+  #[serde(default)]
+  pub product_info: Vec<crate::product::ProductInfo>,
+  pub wine: crate::wine::WineConfig,
+  /// How long a child gets to exit after Ctrl-C is forwarded to it
+  pub shutdown_timeout_secs: u64,
 }
 
 impl Default for Config {
   fn default() -> Self {
     Self {
       region: crate::Region::Global,
-      product: crate::Product::WoW,
-      feature: crate::Feature::Net,
+      product: "wow".to_string(),
+      feature: vec![crate::Feature::Net],
       output_directory: "download".to_string(),
       mega_folder: "https://mega.nz/folder/XQdwFJTR#X8VNWdap7eKtIvmPbpW6sA".to_string(),
+      wow_install_path: None,
       cmd: vec![CommandConfig {
         trigger: "after_error".to_string(),
-        path: "powershell".to_string(),
-        args: vec![
-          "-command".to_string(),
-          "Start-Sleep".to_string(),
-          "-Seconds".to_string(),
-          "5".to_string(),
-        ],
+        action: Some(crate::action::Action::Pause),
+        path: String::new(),
+        args: vec![],
+        seconds: Some(5),
+        to: None,
+        name: None,
+        contents: None,
+        append: false,
+        mode: HookMode::Pty,
+        wait_for_exit: true,
+        cwd: None,
+        timeout_secs: None,
+        required: false,
+        when: When::default(),
       }],
+      output_rule: vec![],
+      launch: crate::launch::LaunchConfig::default(),
+      launch_wow: crate::launch::LaunchWowConfig::default(),
+      wait_for_update: crate::productdb::WaitForUpdateConfig::default(),
+      supervise: crate::supervise::SuperviseConfig::default(),
+      version_source: crate::productdb::VersionSource::default(),
+      prefetch: true,
+      prefetch_poll_secs: 300,
+      product_info: vec![],
+      wine: crate::wine::WineConfig::default(),
+      shutdown_timeout_secs: 10,
     }
   }
 }
 
+/// Deserializes either a single value or a list of values into a `Vec`
+fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+  D: Deserializer<'de>,
+  T: Deserialize<'de>,
+{
+  #[derive(Deserialize)]
+  #[serde(untagged)]
+  enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+  }
+
+  Ok(match OneOrMany::deserialize(deserializer)? {
+    OneOrMany::One(v) => vec![v],
+    OneOrMany::Many(v) => v,
+  })
+}
+
 impl Config {
+  pub fn shutdown_timeout(&self) -> Duration {
+    Duration::from_secs(self.shutdown_timeout_secs)
+  }
+
+  /// Checks the settings that deserializing cannot, so that a broken
+  /// `[[cmd]]` is reported on startup instead of when its trigger fires
+  fn validate(&self) -> Result<()> {
+    use crate::action::Action;
+
+    for (i, cmd) in self.cmd.iter().enumerate() {
+      let missing = match cmd.action {
+        None if cmd.path.is_empty() => Some("`path` or `action`"),
+        Some(Action::Copy) if cmd.path.is_empty() => Some("`path`"),
+        Some(Action::Copy) if cmd.to.is_none() => Some("`to`"),
+        Some(Action::Delete | Action::WriteFile) if cmd.path.is_empty() => Some("`path`"),
+        Some(Action::WriteFile) if cmd.contents.is_none() => Some("`contents`"),
+        Some(Action::KillProcess) if cmd.name.is_none() => Some("`name`"),
+        _ => None,
+      };
+
+      if let Some(missing) = missing {
+        return Err(anyhow!(
+          "[[cmd]] number {} for trigger \"{}\" needs {}",
+          i + 1,
+          cmd.trigger,
+          missing
+        ));
+      }
+    }
+
+    // Built-in products know their executable, so only ones added in config
+    // can miss it
+    if self.launch_wow.enabled {
+      let products = crate::product::ProductTable::with_overrides(&self.product_info);
+      for info in &self.product_info {
+        if products
+          .get(&info.code)
+          .is_some_and(|p| p.executable.is_empty())
+        {
+          return Err(anyhow!(
+            "[[product_info]] \"{}\" needs `executable` while [launch_wow] is enabled",
+            info.code
+          ));
+        }
+      }
+    }
+
+    Ok(())
+  }
+
   /// Get all commands that match the given trigger
   pub fn commands_for_trigger(&self, trigger: &str) -> Vec<&CommandConfig> {
     self.cmd.iter().filter(|c| c.trigger == trigger).collect()
@@ -68,6 +289,7 @@ pub fn load(path: &str) -> Result<Config> {
   };
 
   let config: Config = toml::from_str(&toml_str).context("Could not parse config")?;
+  config.validate()?;
   Ok(config)
 }
 
@@ -0,0 +1,202 @@
+use std::io;
+use std::io::Read;
+use std::sync::LazyLock;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+/// How often `wait_for_key` lets go of the console to check if it was cancelled
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Set while `wait_for_key` runs, so input is handed over without waiting for
+/// the end of the line
+static KEY_MODE: AtomicBool = AtomicBool::new(false);
+
+/// Lines typed into the runner's console. A single thread reads stdin so the
+/// product menu and child processes can take turns consuming input.
+static LINES: LazyLock<Mutex<mpsc::Receiver<String>>> = LazyLock::new(|| {
+  let (tx, rx) = mpsc::channel();
+
+  thread::spawn(move || {
+    let mut stdin = io::stdin();
+    let mut buf = [0; 1024];
+    let mut pending = Vec::new();
+    loop {
+      let n = match stdin.read(&mut buf) {
+        Ok(0) | Err(_) => break,
+        Ok(n) => n,
+      };
+      pending.extend_from_slice(&buf[..n]);
+
+      let mut chunks = Vec::new();
+      while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+        chunks.push(pending.drain(..=end).collect::<Vec<_>>());
+      }
+      if KEY_MODE.load(Ordering::SeqCst) && !pending.is_empty() {
+        chunks.push(std::mem::take(&mut pending));
+      }
+      for chunk in chunks {
+        if tx
+          .send(String::from_utf8_lossy(&chunk).into_owned())
+          .is_err()
+        {
+          return;
+        }
+      }
+    }
+
+    // The last line may not end with a line break
+    if !pending.is_empty() {
+      let _ = tx.send(String::from_utf8_lossy(&pending).into_owned());
+    }
+  });
+
+  Mutex::new(rx)
+});
+
+/// Waits for the next line typed into the console, including its line ending.
+/// Returns `None` once stdin is closed.
+pub fn read_line() -> Option<String> {
+  LINES.lock().ok()?.recv().ok()
+}
+
+/// Like `read_line`, but gives up after `timeout`
+pub fn read_line_timeout(timeout: Duration) -> Result<String, mpsc::RecvTimeoutError> {
+  LINES
+    .lock()
+    .map_err(|_| mpsc::RecvTimeoutError::Disconnected)?
+    .recv_timeout(timeout)
+}
+
+/// Waits for a key to be pressed in the console, for at most `timeout` if set.
+/// Gives up as soon as `cancelled` is set, so an abandoned wait doesn't keep
+/// eating input. Returns whether a key was pressed.
+pub fn wait_for_key(timeout: Option<Duration>, cancelled: &AtomicBool) -> bool {
+  let deadline = timeout.map(|timeout| Instant::now() + timeout);
+  let _mode = KeyMode::enable();
+
+  while !cancelled.load(Ordering::SeqCst) {
+    let wait = match deadline {
+      Some(deadline) => {
+        match deadline.checked_duration_since(Instant::now()) {
+          Some(left) if !left.is_zero() => left.min(POLL_INTERVAL),
+          _ => return false,
+        }
+      },
+      None => POLL_INTERVAL,
+    };
+    match read_line_timeout(wait) {
+      Ok(_) => return true,
+      Err(mpsc::RecvTimeoutError::Timeout) => continue,
+      Err(mpsc::RecvTimeoutError::Disconnected) => return false,
+    }
+  }
+  false
+}
+
+/// Turns off line buffering and echo on the console until dropped. Leaves
+/// the console alone when stdin isn't one.
+struct KeyMode {
+  #[cfg(unix)]
+  saved: Option<libc::termios>,
+  #[cfg(windows)]
+  saved: Option<windows::Win32::System::Console::CONSOLE_MODE>,
+}
+
+impl KeyMode {
+  #[cfg(unix)]
+  fn enable() -> Self {
+    KEY_MODE.store(true, Ordering::SeqCst);
+
+    let mut saved = std::mem::MaybeUninit::<libc::termios>::uninit();
+    // SAFETY: `saved` is only read after tcgetattr filled it in
+    let saved = unsafe {
+      if libc::tcgetattr(libc::STDIN_FILENO, saved.as_mut_ptr()) != 0 {
+        return Self {
+          saved: None,
+        };
+      }
+      saved.assume_init()
+    };
+
+    let mut raw = saved;
+    raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+    raw.c_cc[libc::VMIN] = 1;
+    raw.c_cc[libc::VTIME] = 0;
+    // SAFETY: `raw` is a termios read from the same terminal
+    if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+      return Self {
+        saved: None,
+      };
+    }
+    Self {
+      saved: Some(saved),
+    }
+  }
+
+  #[cfg(windows)]
+  fn enable() -> Self {
+    use windows::Win32::System::Console::CONSOLE_MODE;
+    use windows::Win32::System::Console::ENABLE_ECHO_INPUT;
+    use windows::Win32::System::Console::ENABLE_LINE_INPUT;
+    use windows::Win32::System::Console::GetConsoleMode;
+    use windows::Win32::System::Console::GetStdHandle;
+    use windows::Win32::System::Console::STD_INPUT_HANDLE;
+    use windows::Win32::System::Console::SetConsoleMode;
+
+    KEY_MODE.store(true, Ordering::SeqCst);
+
+    // SAFETY: the handle belongs to this process and outlives the calls
+    let saved = unsafe {
+      let Ok(handle) = GetStdHandle(STD_INPUT_HANDLE) else {
+        return Self {
+          saved: None,
+        };
+      };
+      let mut saved = CONSOLE_MODE::default();
+      if GetConsoleMode(handle, &mut saved).is_err()
+        || SetConsoleMode(handle, saved & !(ENABLE_LINE_INPUT | ENABLE_ECHO_INPUT)).is_err()
+      {
+        return Self {
+          saved: None,
+        };
+      }
+      saved
+    };
+    Self {
+      saved: Some(saved),
+    }
+  }
+}
+
+impl Drop for KeyMode {
+  fn drop(&mut self) {
+    KEY_MODE.store(false, Ordering::SeqCst);
+
+    #[cfg(unix)]
+    if let Some(saved) = &self.saved {
+      // SAFETY: `saved` was read from the same terminal
+      unsafe {
+        libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, saved);
+      }
+    }
+
+    #[cfg(windows)]
+    if let Some(saved) = self.saved {
+      use windows::Win32::System::Console::GetStdHandle;
+      use windows::Win32::System::Console::STD_INPUT_HANDLE;
+      use windows::Win32::System::Console::SetConsoleMode;
+
+      // SAFETY: the handle belongs to this process and outlives the call
+      unsafe {
+        if let Ok(handle) = GetStdHandle(STD_INPUT_HANDLE) {
+          let _ = SetConsoleMode(handle, saved);
+        }
+      }
+    }
+  }
+}
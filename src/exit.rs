@@ -0,0 +1,138 @@
+use std::error::Error;
+use std::fmt;
+
+/// Exit codes returned by the runner. When iMorph itself exits with a
+/// non-zero code it is passed through, unless it would be mistaken for one
+/// of these; see [`Code::for_child`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Code {
+  /// The installed iMorph was already the latest and it exited cleanly
+  UpToDate = 0,
+  /// A new iMorph was installed and it exited cleanly
+  Updated = 100,
+  /// iMorph has not been released for the installed WoW version
+  NoRelease = 101,
+  /// The WoW install or its build info could not be found
+  WowNotFound = 102,
+  /// The iMorph folder could not be reached or the download failed
+  Network = 103,
+  /// iMorph could not be started, or exited with a code the runner reserves
+  ChildFailed = 104,
+  /// Any other error
+  Error = 105,
+  /// A required `before_download` or `before_launch` command failed
+  HookRejected = 106,
+  /// Battle.net was still updating or repairing WoW when waiting for it
+  /// timed out
+  UpdateTimeout = 107,
+  /// The user pressed Ctrl-C
+  Interrupted = 130,
+}
+
+impl Code {
+  /// Every code the runner exits with on its own
+  const ALL: [Code; 10] = [
+    Code::UpToDate,
+    Code::Updated,
+    Code::NoRelease,
+    Code::WowNotFound,
+    Code::Network,
+    Code::ChildFailed,
+    Code::Error,
+    Code::HookRejected,
+    Code::UpdateTimeout,
+    Code::Interrupted,
+  ];
+
+  /// Picks the runner's exit code after iMorph exited with the non-zero
+  /// `exit_code`: iMorph's own code, or `ChildFailed` when that code would
+  /// read as one of the runner's. Unix keeps only the low 8 bits of an exit
+  /// code, so those are what is compared there.
+  pub fn for_child(exit_code: u32) -> i32 {
+    let seen = if cfg!(unix) {
+      exit_code & 0xff
+    } else {
+      exit_code
+    };
+    if Code::ALL.iter().any(|&code| code as u32 == seen) {
+      Code::ChildFailed as i32
+    } else {
+      exit_code as i32
+    }
+  }
+
+  /// Picks the exit code for an error returned by the runner
+  pub fn for_error(e: &anyhow::Error) -> Code {
+    if e
+      .chain()
+      .any(|cause| cause.is::<crate::process::Interrupted>())
+    {
+      return Code::Interrupted;
+    }
+
+    e.chain()
+      .find_map(|cause| cause.downcast_ref::<Tagged>())
+      .map(|tagged| tagged.code)
+      .unwrap_or(Code::Error)
+  }
+}
+
+/// An error tagged with the exit code it should produce. It displays as the
+/// wrapped error.
+#[derive(Debug)]
+struct Tagged {
+  code: Code,
+  error: anyhow::Error,
+}
+
+impl fmt::Display for Tagged {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.error)
+  }
+}
+
+impl Error for Tagged {
+  fn source(&self) -> Option<&(dyn Error + 'static)> {
+    self.error.source()
+  }
+}
+
+pub trait ResultExt<T> {
+  /// Tags the error with the exit code the runner should return for it
+  fn exit_code(self, code: Code) -> anyhow::Result<T>;
+}
+
+impl<T, E> ResultExt<T> for Result<T, E>
+where
+  E: Into<anyhow::Error>,
+{
+  fn exit_code(self, code: Code) -> anyhow::Result<T> {
+    self.map_err(|e| {
+      anyhow::Error::new(Tagged {
+        code,
+        error: e.into(),
+      })
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn passes_through_child_codes() {
+    assert_eq!(Code::for_child(1), 1);
+    assert_eq!(Code::for_child(3), 3);
+    assert_eq!(Code::for_child(108), 108);
+  }
+
+  #[test]
+  fn maps_reserved_child_codes_to_child_failed() {
+    assert_eq!(Code::for_child(101), 104);
+    assert_eq!(Code::for_child(130), 104);
+    if cfg!(unix) {
+      assert_eq!(Code::for_child(256), 104);
+    }
+  }
+}
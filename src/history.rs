@@ -0,0 +1,68 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use serde::Serialize;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use tracing::warn;
+
+/// Something worth remembering about a run
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+  /// iMorph exited unexpectedly and is restarted after `delay_secs`
+  Restart {
+    attempt: u32,
+    exit_code: u32,
+    reason: String,
+    delay_secs: u64,
+  },
+  /// Supervising stopped and iMorph was not restarted again
+  GaveUp {
+    restarts: u32,
+    exit_code: u32,
+    reason: String,
+  },
+}
+
+#[derive(Serialize)]
+struct Entry<'a> {
+  time: String,
+  run_id: Option<&'a str>,
+  #[serde(flatten)]
+  event: &'a Event,
+}
+
+static PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Sets the file the history is appended to
+pub fn init(path: PathBuf) {
+  let _ = PATH.set(path);
+}
+
+/// Appends `event` to the history as a line of JSON
+pub fn record(event: Event) {
+  let Some(path) = PATH.get() else {
+    return;
+  };
+
+  let entry = Entry {
+    time: OffsetDateTime::now_utc()
+      .format(&Rfc3339)
+      .unwrap_or_default(),
+    run_id: crate::transcript::run_id(),
+    event: &event,
+  };
+  let result = serde_json::to_string(&entry)
+    .map_err(std::io::Error::from)
+    .and_then(|line| {
+      let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+      writeln!(file, "{}", line)
+    });
+
+  if let Err(e) = result {
+    warn!(error = %e, "Failed to record run history");
+  }
+}
@@ -0,0 +1,84 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use anyhow::anyhow;
+use serde::Deserialize;
+
+/// The executable iMorph archives have shipped with so far
+const DEFAULT_EXE: &str = "RuniMorph.exe";
+
+/// How iMorph is started. Every value accepts `{name}` placeholders.
+#[derive(Debug, Default, Deserialize, Clone)]
+#[serde(default)]
+pub struct LaunchConfig {
+  /// Executable in the output directory. Detected when not set.
+  pub exe: Option<String>,
+  pub args: Vec<String>,
+  /// Extra environment variables
+  pub env: BTreeMap<String, String>,
+  /// Working directory. Defaults to the output directory.
+  pub cwd: Option<String>,
+}
+
+/// When WoW is started relative to iMorph
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WowOrder {
+  BeforeImorph,
+  /// Right after iMorph was started
+  #[default]
+  AfterImorph,
+}
+
+/// Starting WoW along with iMorph
+#[derive(Debug, Default, Deserialize, Clone)]
+#[serde(default)]
+pub struct LaunchWowConfig {
+  pub enabled: bool,
+  pub order: WowOrder,
+  /// Arguments passed to WoW. Accepts `{name}` placeholders.
+  pub args: Vec<String>,
+}
+
+/// Finds the executable to run in `dir`. `RuniMorph.exe` is preferred, then
+/// executables named after iMorph, then the only executable in the archive.
+pub fn find_exe(dir: &Path) -> Result<PathBuf> {
+  let default = dir.join(DEFAULT_EXE);
+  if default.is_file() {
+    return Ok(default);
+  }
+
+  let mut exes: Vec<PathBuf> = fs::read_dir(dir)?
+    .filter_map(|e| e.ok())
+    .map(|e| e.path())
+    .filter(|p| {
+      p.is_file()
+        && p
+          .extension()
+          .is_some_and(|ext| ext.eq_ignore_ascii_case("exe"))
+    })
+    .collect();
+  exes.sort();
+
+  let named_imorph = exes.iter().find(|p| {
+    p.file_name()
+      .and_then(|n| n.to_str())
+      .is_some_and(|n| n.to_lowercase().contains("imorph"))
+  });
+
+  match (named_imorph, exes.as_slice()) {
+    (Some(exe), _) => Ok(exe.clone()),
+    (None, [exe]) => Ok(exe.clone()),
+    (None, []) => Err(anyhow!("No executable found in {:?}", dir)),
+    (None, _) => {
+      Err(anyhow!(
+        "Found several executables in {:?}, set `exe` in [launch] to pick one: {:?}",
+        dir,
+        exes
+      ))
+    },
+  }
+}
@@ -1,12 +1,19 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
 use std::fmt;
 use std::fs::File as StdFile;
 use std::io;
+use std::io::Write;
 use std::path::Path;
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::Duration;
+use std::time::Instant;
 
 use anyhow::Context;
 use anyhow::Result;
 use anyhow::anyhow;
+use exit::ResultExt;
 use semver::Version;
 use serde::Deserialize;
 use serde::Serialize;
@@ -18,18 +25,34 @@ use tokio::io::AsyncWriteExt;
 use tracing::Level;
 use tracing::error;
 use tracing::info;
+use tracing::warn;
+use tracing_subscriber::Layer;
 use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::filter::filter_fn;
 use tracing_subscriber::fmt::time::OffsetTime;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
+use transcript::Transcript;
 use zip::read::ZipArchive;
 use zip::result::ZipResult;
 
+mod action;
 mod buildinfo;
 mod config;
+mod console;
+mod exit;
+mod history;
+mod launch;
 mod mega_helper;
+mod output_rule;
+mod process;
+mod product;
 mod productdb;
-mod pty;
+mod supervise;
+mod tact;
+mod template;
+mod transcript;
+mod wine;
 
 #[derive(PartialEq, Eq, Copy, Clone, Debug, Deserialize, Serialize)]
 enum Region {
@@ -37,6 +60,30 @@ enum Region {
   Global,
   #[serde(rename = "china")]
   China,
+  /// Detected from the Battle.net product database at startup
+  #[serde(rename = "auto")]
+  Auto,
+}
+
+impl Region {
+  /// Maps a Battle.net region code (e.g. "us", "eu", "cn") to a `Region`
+  fn from_battlenet(code: &str) -> Option<Region> {
+    match code.to_lowercase().as_str() {
+      "cn" => Some(Region::China),
+      "us" | "eu" | "kr" | "tw" | "sg" => Some(Region::Global),
+      _ => None,
+    }
+  }
+}
+
+impl fmt::Display for Region {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Region::Global => write!(f, "global"),
+      Region::China => write!(f, "china"),
+      Region::Auto => write!(f, "auto"),
+    }
+  }
 }
 
 impl FromStr for Region {
@@ -51,35 +98,9 @@ impl FromStr for Region {
   }
 }
 
-#[derive(PartialEq, Eq, Copy, Clone, Debug, Deserialize, Serialize)]
-enum Product {
-  #[serde(rename = "wow")]
-  WoW,
-  #[serde(rename = "wow_classic")]
-  WoWClassic,
-  #[serde(rename = "wow_classic_era")]
-  WoWClassicEra,
-  #[serde(rename = "wow_beta")]
-  WoWBeta,
-  #[serde(rename = "wowxptr")]
-  WoWXPtr,
-}
-
-impl fmt::Display for Product {
-  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-    match self {
-      Product::WoW => write!(f, "wow"),
-      Product::WoWClassic => write!(f, "wow_classic"),
-      Product::WoWClassicEra => write!(f, "wow_classic_era"),
-      Product::WoWBeta => write!(f, "wow_beta"),
-      Product::WoWXPtr => write!(f, "wowxptr"),
-    }
-  }
-}
-
 #[derive(PartialEq, Eq, Copy, Clone, Debug, Deserialize, Serialize)]
 enum Feature {
-  #[serde(rename = "")]
+  #[serde(rename = "", alias = "none")]
   None,
   #[serde(rename = "net")]
   Net,
@@ -100,25 +121,34 @@ impl FromStr for Feature {
   }
 }
 
+impl fmt::Display for Feature {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Feature::None => write!(f, "none"),
+      Feature::Net => write!(f, "net"),
+      Feature::Menu => write!(f, "menu"),
+    }
+  }
+}
+
 #[derive(Debug)]
 pub struct ImorphEntry {
-  // feature: Feature,
+  feature: Feature,
   wow_version: String,
   imorph_version: String,
   // region: Region,
-  // product: Product,
   node: megalib::Node,
 }
 
 /// Extracts a zip file to the specified directory
-fn unzip_file(zip_path: impl AsRef<Path>, extract_to: &str) -> ZipResult<()> {
+fn unzip_file(zip_path: impl AsRef<Path>, extract_to: impl AsRef<Path>) -> ZipResult<()> {
   let file = StdFile::open(zip_path)?;
   let mut archive = ZipArchive::new(file)?;
 
   for i in 0..archive.len() {
     let mut file = archive.by_index(i)?;
     let name = file.name().to_owned();
-    let outpath = Path::new(extract_to).join(file.mangled_name());
+    let outpath = extract_to.as_ref().join(file.mangled_name());
 
     // Check if imorph.conf already exists, and skip if so
     if name == "imorph.conf" && outpath.exists() {
@@ -141,6 +171,20 @@ fn unzip_file(zip_path: impl AsRef<Path>, extract_to: &str) -> ZipResult<()> {
   Ok(())
 }
 
+const LOG_FILE: &str = "imorph-runner.log";
+
+/// Where the log, transcripts and run history are kept
+fn data_dir() -> PathBuf {
+  dirs::data_local_dir()
+    .unwrap_or_else(|| PathBuf::from("."))
+    .join("imorph-runner")
+}
+
+/// The runner's log file
+pub fn log_path() -> PathBuf {
+  data_dir().join(LOG_FILE)
+}
+
 fn init_tracing() {
   let timer_format = format_description!("[year]-[month]-[day] [hour]:[minute]");
   let local_offset = UtcOffset::current_local_offset().unwrap_or(UtcOffset::UTC);
@@ -148,14 +192,14 @@ fn init_tracing() {
   let timer = OffsetTime::new(local_offset, timer_format);
 
   // Set up file logging in APPDATA
-  let appdata_dir = dirs::data_local_dir()
-    .unwrap_or_else(|| std::path::PathBuf::from("."))
-    .join("imorph-runner");
+  let appdata_dir = data_dir();
 
   // Create the log directory if it doesn't exist
   std::fs::create_dir_all(&appdata_dir).ok();
+  transcript::init(appdata_dir.join("transcripts"), local_offset);
+  history::init(appdata_dir.join("history.jsonl"));
 
-  let file_appender = tracing_appender::rolling::never(&appdata_dir, "imorph-runner.log");
+  let file_appender = tracing_appender::rolling::never(&appdata_dir, LOG_FILE);
   let (non_blocking, _guard) = tracing_appender::non_blocking(file_appender);
 
   // Keep the guard alive for the duration of the program
@@ -178,7 +222,9 @@ fn init_tracing() {
         .with_writer(std::io::stdout)
         .with_timer(timer)
         .with_span_events(tracing_subscriber::fmt::format::FmtSpan::NONE)
-        .compact(),
+        .compact()
+        // Child output is printed to the terminal as is
+        .with_filter(filter_fn(|meta| meta.target() != process::OUTPUT_TARGET)),
     )
     .with(terminal_filter)
     .init();
@@ -210,6 +256,7 @@ fn setup_environment() -> Result<()> {
   #[cfg(windows)]
   enable_ansi_support()?;
   init_tracing();
+  info!(run_id = transcript::run_id(), "Starting run");
   Ok(())
 }
 
@@ -222,10 +269,237 @@ async fn ensure_output_directory(path: &str) -> Result<()> {
   Ok(())
 }
 
-/// Retrieves the WoW build info for the specified product
-async fn get_wow_build_info(product: Product) -> Result<buildinfo::BuildInfoEntry> {
+/// Asks the user to pick one of the installed products on the console
+fn prompt_for_product<'a>(
+  installed: &[(&'a product::ProductInfo, String)],
+) -> Result<&'a product::ProductInfo> {
+  println!("Multiple WoW products are installed:");
+  for (i, (info, version)) in installed.iter().enumerate() {
+    println!("  {}) {} [{}] ({})", i + 1, info.name, info.code, version);
+  }
+
+  loop {
+    print!("Select a product [1-{}]: ", installed.len());
+    io::stdout().flush()?;
+
+    let Some(line) = console::read_line() else {
+      return Err(anyhow!("No product selected"));
+    };
+
+    match line.trim().parse::<usize>() {
+      Ok(n) if (1..=installed.len()).contains(&n) => return Ok(installed[n - 1].0),
+      _ => println!("Invalid selection \"{}\"", line.trim()),
+    }
+  }
+}
+
+/// Looks up the configured product, resolving `auto` to the only installed
+/// product or asking the user to choose when more than one is installed
+fn resolve_product<'a>(
+  products: &'a product::ProductTable,
+  code: &str,
+  db: Option<&productdb::Database>,
+) -> Result<&'a product::ProductInfo> {
+  if code != product::AUTO {
+    return products.get(code).ok_or_else(|| {
+      anyhow!(
+        "Unknown product \"{}\". Define it with a [[product_info]] entry in the config.",
+        code
+      )
+    });
+  }
+
+  info!("Detecting installed products from Battle.net product database");
+  let db = db.ok_or_else(|| anyhow!("Failed to read Battle.net product database"))?;
+
+  let installed: Vec<(&product::ProductInfo, String)> = db
+    .pdb
+    .product_installs
+    .iter()
+    .filter_map(|install| {
+      let info = products.get(&install.product_code)?;
+      let version = productdb::base_state(install)
+        .map(|base| base.current_version_str.clone())
+        .unwrap_or_default();
+      Some((info, version))
+    })
+    .collect();
+
+  match installed.as_slice() {
+    [] => Err(anyhow!("No installed WoW products found")),
+    [(info, version)] => {
+      info!(product = info.code, version = version, "Detected product");
+      Ok(*info)
+    },
+    _ => prompt_for_product(&installed),
+  }
+}
+
+/// Resolves `Region::Auto` using the regions Battle.net reports for the
+/// product, falling back to `Region::Global` when they are missing or disagree
+fn resolve_region(
+  region: Region,
+  product: &product::ProductInfo,
+  db: Option<&productdb::Database>,
+) -> Region {
+  if region != Region::Auto {
+    return region;
+  }
+
+  info!("Detecting region from Battle.net product database");
+  let codes = db
+    .and_then(|db| db.find_install(&product.code))
+    .map(productdb::install_regions)
+    .unwrap_or_default();
+
+  let mut regions: Vec<Region> = codes
+    .iter()
+    .filter_map(|code| Region::from_battlenet(code))
+    .collect();
+  regions.dedup();
+
+  match regions.as_slice() {
+    [region] => {
+      info!(region = ?region, codes = ?codes, "Detected region");
+      *region
+    },
+    _ => {
+      warn!(
+        codes = ?codes,
+        "Could not determine region from Battle.net, falling back to global"
+      );
+      Region::Global
+    },
+  }
+}
+
+/// Finds the Wine prefix iMorph and WoW run in: the configured one, the one
+/// the WoW install is in, or the one Battle.net's `product.db` was found in.
+/// There is none on Windows.
+fn resolve_wine_prefix(
+  cfg: &config::Config,
+  wow_path: &Path,
+  db: Option<&productdb::Database>,
+) -> Option<PathBuf> {
+  if cfg!(windows) {
+    return None;
+  }
+
+  let prefix = cfg
+    .wine
+    .prefix()
+    .or_else(|| wine::prefix_of(wow_path))
+    .or_else(|| db?.location.wine_prefix.clone());
+  match &prefix {
+    Some(prefix) => info!(prefix = %prefix.display(), "Using Wine prefix"),
+    None => warn!("Could not find the Wine prefix of the WoW install, using Wine's default"),
+  }
+  prefix
+}
+
+/// A WoW install and the version of its selected build
+struct WowInstall {
+  path: PathBuf,
+  /// The version iMorph is picked for. It differs from the one in
+  /// `.build.info` when `product.db` disagrees and `version_source` prefers
+  /// it.
+  version: String,
+}
+
+/// Waits until Battle.net has no update or repair running for the product,
+/// and the product is playable again after one, so `.build.info` has the
+/// version that will actually run. `db` is read
+/// again while waiting, and the last read is returned. Does nothing when
+/// `product.db` cannot be read.
+async fn wait_for_battlenet_update(
+  cfg: &productdb::WaitForUpdateConfig,
+  product: &product::ProductInfo,
+  db: Option<productdb::Database>,
+) -> Result<Option<productdb::Database>> {
+  if !cfg.enabled {
+    return Ok(db);
+  }
+  let Some(mut db) = db else {
+    info!("Not checking for Battle.net updates: product.db could not be read");
+    return Ok(None);
+  };
+
+  let timeout = Duration::from_secs(cfg.timeout_secs);
+  let poll = Duration::from_secs(cfg.poll_secs.max(1));
+  let start = Instant::now();
+  let mut waited = false;
+  let mut seen_update = false;
+  loop {
+    let Some(status) = db
+      .find_install(&product.code)
+      .map(productdb::UpdateStatus::of)
+    else {
+      info!(
+        product = product.code,
+        "Product not in product.db, not waiting for updates"
+      );
+      return Ok(Some(db));
+    };
+
+    seen_update |= status.is_updating();
+    if status.is_ready(seen_update) {
+      if waited {
+        info!("Battle.net finished updating");
+      }
+      if !status.playable {
+        warn!(
+          "product.db says WoW is not playable although nothing is updating it. Carrying on, since \
+           Battle.net leaves this flag behind when it is closed."
+        );
+      }
+      return Ok(Some(db));
+    }
+    if start.elapsed() >= timeout {
+      return Err(anyhow!(
+        "Battle.net was still updating {} after {:?}",
+        product.name,
+        timeout
+      ))
+      .exit_code(exit::Code::UpdateTimeout);
+    }
+
+    info!(
+      operation = ?status.operation,
+      playable = status.playable,
+      update_complete = status.update_complete,
+      progress = status.progress.map(|p| format!("{:.0}%", p * 100.0)),
+      remaining_mb = status.download_remaining.map(|b| b / 1024 / 1024),
+      "Waiting for Battle.net to finish updating"
+    );
+
+    waited = true;
+    tokio::select! {
+      _ = tokio::time::sleep(poll) => {},
+      _ = process::interrupted() => return Err(process::Interrupted.into()),
+    }
+
+    db = match db.reload() {
+      Ok(fresh) => fresh,
+      Err(e) => {
+        warn!(error = %e, "Could not read Battle.net product database");
+        return Ok(Some(db));
+      },
+    };
+  }
+}
+
+/// Retrieves the active WoW build info for the specified product and region.
+/// Its version is cross-checked against `product.db`, and `version_source`
+/// decides which one is used when they disagree.
+async fn get_wow_build_info(
+  product: &product::ProductInfo,
+  region: Region,
+  configured_path: Option<&Path>,
+  db: Option<&productdb::Database>,
+  version_source: productdb::VersionSource,
+) -> Result<WowInstall> {
   info!("Finding WoW install path");
-  let install_path = buildinfo::find_wow_install_path(product)?;
+  let install_path = buildinfo::find_wow_install_path(product, configured_path, db)?;
   let buildinfo_path = install_path.join(".build.info");
 
   info!(
@@ -242,99 +516,418 @@ async fn get_wow_build_info(product: Product) -> Result<buildinfo::BuildInfoEntr
     ));
   }
 
-  buildinfos
-    .into_iter()
-    .find(|v| v.product == product)
-    .ok_or_else(|| anyhow!("Could not find product: {}", product))
+  let entry = buildinfo::select_entry(buildinfos, &product.build_info_product, region)
+    .ok_or_else(|| anyhow!("Could not find product: {}", product.build_info_product))?;
+  let version = cross_check_wow_version(&entry.version, &install_path, product, db, version_source);
+
+  info!(
+    branch = entry.branch,
+    active = entry.active,
+    version,
+    "Selected WoW build"
+  );
+
+  Ok(WowInstall {
+    path: install_path,
+    version,
+  })
 }
 
-/// Finds the latest iMorph entry matching the criteria
+/// Compares the WoW version from `.build.info` with the one Battle.net
+/// recorded in `product.db` for the same install, and returns the version
+/// `source` picks. Without a usable `product.db` the `.build.info` version is
+/// kept.
+fn cross_check_wow_version(
+  build_info_version: &str,
+  install_path: &Path,
+  product: &product::ProductInfo,
+  db: Option<&productdb::Database>,
+  source: productdb::VersionSource,
+) -> String {
+  let Some(db) = db else {
+    info!("Not cross-checking the WoW version: product.db could not be read");
+    return build_info_version.to_string();
+  };
+
+  let Some(install) = db.find_install(&product.code) else {
+    info!(
+      product = product.code,
+      "Not cross-checking the WoW version: the product is not listed in product.db"
+    );
+    return build_info_version.to_string();
+  };
+
+  let same_install = |path: PathBuf| {
+    match (path.canonicalize(), install_path.canonicalize()) {
+      (Ok(a), Ok(b)) => a == b,
+      _ => path == install_path,
+    }
+  };
+  if let Some(settings) = &install.settings
+    && !settings.install_path.is_empty()
+    && !same_install(db.location.install_path(&settings.install_path))
+  {
+    info!(
+      product_db_path = settings.install_path,
+      "Not cross-checking the WoW version: product.db describes a different install"
+    );
+    return build_info_version.to_string();
+  }
+
+  let Some(product_db_version) = productdb::current_version(install) else {
+    info!("Not cross-checking the WoW version: product.db does not record one");
+    return build_info_version.to_string();
+  };
+
+  if product_db_version == build_info_version {
+    info!(
+      version = build_info_version,
+      "product.db agrees on the WoW version"
+    );
+    return build_info_version.to_string();
+  }
+
+  let version = source.pick(build_info_version, product_db_version);
+  warn!(
+    build_info = build_info_version,
+    product_db = product_db_version,
+    version_source = %source,
+    using = version,
+    ".build.info and product.db disagree on the WoW version. A failed repair can leave a stale \
+     .build.info behind; repair WoW in Battle.net or set version_source to pick the right one."
+  );
+  version.to_string()
+}
+
+/// Finds the latest iMorph entry for the first feature in `features` that has
+/// been released for this WoW version
 async fn find_latest_imorph_entry(
   mh: &mega_helper::MegaHelper,
   region: Region,
-  product: Product,
-  feature: Feature,
+  product: &product::ProductInfo,
+  features: &[Feature],
   wow_version: &str,
 ) -> Result<ImorphEntry> {
   info!("Fetching latest iMorph info");
   let mut entries = mh
-    .fetch_entries(region, product, feature, wow_version)
+    .fetch_entries(region, &product.folder, wow_version)
     .await?;
 
   if entries.is_empty() {
     return Err(anyhow!(
       "iMorph has not been released for the latest WoW version={}.",
       wow_version
-    ));
+    ))
+    .exit_code(exit::Code::NoRelease);
   }
 
   // Find the entry with the greatest imorph_version according to semantic versioning
   let parse_version = |v: &str| Version::parse(v).unwrap_or_else(|_| Version::new(0, 0, 0));
 
-  let max_index = entries
-    .iter()
-    .enumerate()
-    .max_by(|(_, a), (_, b)| {
-      parse_version(&a.imorph_version).cmp(&parse_version(&b.imorph_version))
-    })
-    .map(|(idx, _)| idx)
-    .unwrap_or(0);
+  for feature in features {
+    let latest = entries
+      .iter()
+      .enumerate()
+      .filter(|(_, e)| e.feature == *feature)
+      .max_by(|(_, a), (_, b)| {
+        parse_version(&a.imorph_version).cmp(&parse_version(&b.imorph_version))
+      })
+      .map(|(idx, _)| idx);
+
+    if let Some(idx) = latest {
+      let entry = entries.swap_remove(idx);
+      info!(
+        feature = %entry.feature,
+        imorph_version = entry.imorph_version,
+        "Selected iMorph feature"
+      );
+      return Ok(entry);
+    }
+
+    info!(feature = %feature, "No iMorph release for feature");
+  }
 
-  Ok(entries.remove(max_index))
+  let available: Vec<String> = entries.iter().map(|e| e.feature.to_string()).collect();
+  Err(anyhow!(
+    "iMorph has not been released for WoW version={} with any of the configured features. Available: {}",
+    wow_version,
+    available.join(", ")
+  ))
+  .exit_code(exit::Code::NoRelease)
 }
 
-/// Reads the version file and returns (imorph_version, wow_version)
-async fn read_version_file(version_path: &Path) -> Result<(String, String)> {
+/// The versions recorded in the version file for the current download
+#[derive(Debug, Default)]
+struct DownloadedVersion {
+  imorph_version: String,
+  wow_version: String,
+  feature: String,
+}
+
+/// Reads the version file (`imorph_version|wow_version|feature`)
+async fn read_version_file(version_path: &Path) -> Result<DownloadedVersion> {
   info!(path = version_path.to_str(), "Opening version file");
 
   match fs::File::open(version_path).await {
     Ok(mut file) => {
       let mut contents = String::new();
       file.read_to_string(&mut contents).await?;
-      let contents = contents.trim().to_string();
-      Ok(
-        contents
-          .split_once("|")
-          .map(|v| (v.0.to_string(), v.1.to_string()))
-          .unwrap_or((String::new(), String::new())),
-      )
+      let mut parts = contents.trim().splitn(3, '|').map(|v| v.to_string());
+      Ok(DownloadedVersion {
+        imorph_version: parts.next().unwrap_or_default(),
+        wow_version: parts.next().unwrap_or_default(),
+        feature: parts.next().unwrap_or_default(),
+      })
     },
-    Err(e) if e.kind() == io::ErrorKind::NotFound => Ok((String::new(), String::new())),
+    Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(DownloadedVersion::default()),
     Err(e) => Err(anyhow!(e)),
   }
 }
 
 /// Checks if we already have the latest version downloaded
 fn is_already_downloaded(
-  downloaded_imorph_version: &str,
-  downloaded_wow_version: &str,
+  downloaded: &DownloadedVersion,
   entry: &ImorphEntry,
-  buildinfo: &buildinfo::BuildInfoEntry,
+  wow_version: &str,
 ) -> bool {
-  downloaded_imorph_version == entry.imorph_version && downloaded_wow_version == buildinfo.version
+  downloaded.imorph_version == entry.imorph_version
+    && downloaded.wow_version == wow_version
+    && downloaded.feature == entry.feature.to_string()
+}
+
+/// Folder in the output directory that keeps downloaded iMorph zips, one
+/// per release
+const CACHE_DIR: &str = "cache";
+
+/// Returns the cached zip of an iMorph release. Its name is the name of the
+/// release in the mega folder, which includes the iMorph and WoW versions.
+fn cached_zip_path(output_dir: &Path, entry: &ImorphEntry) -> PathBuf {
+  output_dir.join(CACHE_DIR).join(&entry.node.name)
 }
 
-/// Downloads and extracts the iMorph zip file
-async fn download_and_extract_imorph(
+/// Downloads the iMorph zip file into the cache unless it is already there,
+/// and returns its path
+async fn download_imorph(
   mh: &mega_helper::MegaHelper,
   entry: &ImorphEntry,
   output_dir: &Path,
-) -> Result<()> {
-  let download_path = output_dir.join("download.zip");
+) -> Result<PathBuf> {
+  let download_path = cached_zip_path(output_dir, entry);
+  if download_path.exists() {
+    info!(
+      path = download_path.to_str(),
+      "Using cached iMorph download"
+    );
+    return Ok(download_path);
+  }
 
-  info!(path = download_path.to_str(), "Removing old downloaded zip");
-  std::fs::remove_file(&download_path).ok();
+  fs::create_dir_all(output_dir.join(CACHE_DIR)).await?;
+  // Downloads to a separate file first so an interrupted download is never
+  // mistaken for a cached one
+  let partial_path = download_path.with_extension("zip.part");
+  if partial_path.exists() {
+    info!(
+      path = partial_path.to_str(),
+      "Removing an interrupted download"
+    );
+    fs::remove_file(&partial_path).await?;
+  }
 
   info!(
     imorph_version = entry.imorph_version,
     wow_version = entry.wow_version,
     "Downloading iMorph"
   );
-  mh.download(&entry.node, &download_path).await?;
+  mh.download(&entry.node, &partial_path)
+    .await
+    .exit_code(exit::Code::Network)?;
+  fs::rename(&partial_path, &download_path).await?;
 
-  info!(path = download_path.to_str(), "Unzipping downloaded zip");
-  unzip_file(&download_path, "download").context("Failed to unzip file")?;
+  Ok(download_path)
+}
+
+/// The WoW version in the name of an iMorph release, e.g. `11.0.5.57388` in
+/// `iMorph-1.4.2(Net)[11.0.5.57388].zip`
+fn release_wow_version(name: &str) -> Option<&str> {
+  let (_, rest) = name.rsplit_once('[')?;
+  let (inner, _) = rest.split_once(']')?;
+  inner.rsplit(' ').next()
+}
+
+/// Removes what is no longer needed once `entry` is extracted: cached
+/// releases for older WoW versions, interrupted downloads, and the
+/// `download.zip` that runners without a cache left in the output
+/// directory. Releases for newer WoW versions were pre-fetched and are kept.
+/// Failures are only logged.
+async fn prune_cache(output_dir: &Path, entry: &ImorphEntry) {
+  let mut stale = vec![output_dir.join("download.zip")];
+  match std::fs::read_dir(output_dir.join(CACHE_DIR)) {
+    Ok(dir) => {
+      for file in dir.filter_map(|f| f.ok()) {
+        let name = file.file_name().to_string_lossy().into_owned();
+        let newer = release_wow_version(&name).is_some_and(|version| {
+          productdb::compare_versions(version, &entry.wow_version) == std::cmp::Ordering::Greater
+        });
+        if name != entry.node.name && (!newer || name.ends_with(".part")) {
+          stale.push(file.path());
+        }
+      }
+    },
+    Err(e) => warn!(error = %e, "Could not list cached iMorph downloads"),
+  }
+
+  for path in stale.iter().filter(|p| p.exists()) {
+    info!(path = path.to_str(), "Removing old iMorph download");
+    if let Err(e) = fs::remove_file(path).await {
+      warn!(path = path.to_str(), error = %e, "Could not remove old iMorph download");
+    }
+  }
+}
+
+/// Finds the WoW version Battle.net is downloading ahead of a patch, if it
+/// differs from the installed one. `product.db` only records the build
+/// config of the download, so its version is looked up on the patch server.
+async fn find_pending_wow_version(
+  product: &product::ProductInfo,
+  db: &productdb::Database,
+  wow_version: &str,
+) -> Result<Option<String>> {
+  let Some(install) = db.find_install(&product.code) else {
+    return Ok(None);
+  };
 
+  for config in productdb::background_downloads(install) {
+    let version = tact::version_for_build_config(
+      &config.region,
+      &product.build_info_product,
+      &config.build_config,
+    )
+    .await?;
+
+    if let Some(version) = version.filter(|v| v != wow_version) {
+      info!(
+        wow_version = version,
+        complete = productdb::base_state(install).is_some_and(|b| b.background_download_complete),
+        "Battle.net is downloading a WoW patch ahead of time"
+      );
+      return Ok(Some(version));
+    }
+  }
+
+  Ok(None)
+}
+
+/// Where one pre-fetch check got to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Prefetch {
+  /// The iMorph for the upcoming patch is in the cache
+  Done,
+  /// Battle.net is not downloading a patch ahead of time
+  NoPatch,
+  /// A patch is on its way but its iMorph has not been released yet
+  NotReleased,
+  /// The check failed and is tried again
+  Failed,
+}
+
+/// Downloads the iMorph for the WoW patch Battle.net is downloading ahead of
+/// time into the cache, so it is ready once the patch is installed. Checks
+/// again every `prefetch_poll_secs` until the download is done; the caller
+/// stops it when iMorph exits. `db` is read again for every check, while
+/// the iMorph folder is only opened again when the patch's iMorph has not
+/// been released yet. Failures are only logged.
+async fn prefetch_imorph(
+  mh: &mega_helper::MegaHelper,
+  cfg: &config::Config,
+  region: Region,
+  product: &product::ProductInfo,
+  wow_version: &str,
+  db: Option<&productdb::Database>,
+) {
+  if !cfg.prefetch {
+    return;
+  }
+  let Some(mut db) = db.cloned() else {
+    info!("Not pre-fetching iMorph: product.db could not be read");
+    return;
+  };
+
+  let poll = Duration::from_secs(cfg.prefetch_poll_secs.max(1));
+  let mut reopened = None;
+  loop {
+    let mh = reopened.as_ref().unwrap_or(mh);
+    let state = try_prefetch_imorph(mh, cfg, region, product, wow_version, &db).await;
+    if state == Prefetch::Done {
+      return;
+    }
+
+    tokio::time::sleep(poll).await;
+    // The folder is listed when it is opened, so new releases only show up
+    // in a fresh one
+    if state == Prefetch::NotReleased {
+      match mega_helper::MegaHelper::try_new(&cfg.mega_folder).await {
+        Ok(mh) => reopened = Some(mh),
+        Err(e) => warn!(error = %e, "Could not open the iMorph folder again"),
+      }
+    }
+    match db.reload() {
+      Ok(fresh) => db = fresh,
+      Err(e) => warn!(error = %e, "Could not read Battle.net product database"),
+    }
+  }
+}
+
+/// Checks once for a WoW patch downloaded ahead of time and pre-fetches its
+/// iMorph
+async fn try_prefetch_imorph(
+  mh: &mega_helper::MegaHelper,
+  cfg: &config::Config,
+  region: Region,
+  product: &product::ProductInfo,
+  wow_version: &str,
+  db: &productdb::Database,
+) -> Prefetch {
+  let pending = match find_pending_wow_version(product, db, wow_version).await {
+    Ok(Some(version)) => version,
+    Ok(None) => return Prefetch::NoPatch,
+    Err(e) => {
+      warn!(error = %e, "Could not check for a WoW patch downloaded ahead of time");
+      return Prefetch::Failed;
+    },
+  };
+
+  let entry = match find_latest_imorph_entry(mh, region, product, &cfg.feature, &pending).await {
+    Ok(entry) => entry,
+    Err(e) if exit::Code::for_error(&e) == exit::Code::NoRelease => {
+      info!(wow_version = pending, "Nothing to pre-fetch yet");
+      return Prefetch::NotReleased;
+    },
+    Err(e) => {
+      warn!(wow_version = pending, error = %e, "Could not look for iMorph to pre-fetch");
+      return Prefetch::Failed;
+    },
+  };
+
+  match download_imorph(mh, &entry, Path::new(&cfg.output_directory)).await {
+    Ok(path) => {
+      info!(
+        path = path.to_str(),
+        wow_version = pending,
+        "Pre-fetched iMorph for the upcoming WoW patch"
+      );
+      Prefetch::Done
+    },
+    Err(e) => {
+      warn!(error = %e, "Could not pre-fetch iMorph");
+      Prefetch::Failed
+    },
+  }
+}
+
+/// Extracts the downloaded iMorph zip file into the output directory
+fn extract_imorph(download_path: &Path, output_dir: &Path) -> Result<()> {
+  info!(path = download_path.to_str(), "Unzipping downloaded zip");
+  unzip_file(download_path, output_dir).context("Failed to unzip file")?;
   Ok(())
 }
 
@@ -351,21 +944,254 @@ async fn update_version_file(version_path: &Path, entry: &ImorphEntry) -> Result
     .context("Failed to create version file")?;
 
   file
-    .write_all(format!("{}|{}", entry.imorph_version, entry.wow_version).as_bytes())
+    .write_all(
+      format!(
+        "{}|{}|{}",
+        entry.imorph_version, entry.wow_version, entry.feature
+      )
+      .as_bytes(),
+    )
     .await
     .context("Failed to write version data")?;
 
   Ok(())
 }
 
-/// Runs the iMorph executable
-fn run_imorph(output_dir: &Path, cmd_path: &Path) -> Result<()> {
-  info!(path = cmd_path.to_str(), "Running iMorph");
-  pty::run_command(output_dir, cmd_path, &[], "[imorph] ").context("Failed to run command")?;
+/// Runs the iMorph executable as configured in `[launch]`, through Wine when
+/// not on Windows, and returns its exit code. `vars` fill the placeholders in
+/// the launch settings.
+async fn run_imorph(
+  output_dir: &Path,
+  cfg: &config::Config,
+  wine_prefix: Option<&Path>,
+  db: Option<&productdb::Database>,
+  vars: &HashMap<String, String>,
+) -> Result<u32> {
+  let launch = &cfg.launch;
+  let cmd_path = match &launch.exe {
+    Some(exe) => output_dir.join(template::render(exe, vars)),
+    None => launch::find_exe(output_dir).exit_code(exit::Code::ChildFailed)?,
+  };
+  let args: Vec<String> = launch
+    .args
+    .iter()
+    .map(|arg| template::render(arg, vars))
+    .collect();
+  info!(path = cmd_path.to_str(), args = ?args, "Running iMorph");
+
+  let mut cmd = if cfg!(windows) {
+    std::process::Command::new(&cmd_path)
+  } else {
+    let cmd_path = std::path::absolute(&cmd_path)?;
+    info!(command = cfg.wine.command, prefix = ?wine_prefix, "Using Wine");
+    wine::command(&cfg.wine, wine_prefix, &cmd_path)
+  };
+  cmd.args(&args);
+  for (key, value) in &launch.env {
+    cmd.env(key, template::render(value, vars));
+  }
+  match &launch.cwd {
+    Some(cwd) => cmd.current_dir(template::render(cwd, vars)),
+    None => cmd.current_dir(output_dir),
+  };
+
+  let rules = output_rule::OutputRules::compile(&cfg.output_rule)?;
+  // Hooks run alongside iMorph so its output keeps flowing while they do
+  let (events_tx, mut events_rx) = tokio::sync::mpsc::unbounded_channel();
+  let on_line = move |line: &process::Line| {
+    let mut action = process::LineAction::default();
+    for event in rules.matches(&line.text) {
+      info!(event = event.name, "Output rule matched");
+      if let Some(level) = event.level {
+        action.level = level;
+      }
+      if event.terminate {
+        warn!(event = event.name, "Output rule terminates iMorph");
+        action.kill = true;
+      }
+      let _ = events_tx.send(event);
+    }
+    action
+  };
+
+  let options = process::RunOptions {
+    log_prefix: "[imorph] ",
+    shutdown_timeout: cfg.shutdown_timeout(),
+    transcript: Some("imorph"),
+    ..Default::default()
+  };
+  run_required_commands_for_trigger(cfg, "before_launch", vars)
+    .await
+    .context("Launch rejected by a before_launch command")
+    .exit_code(exit::Code::HookRejected)?;
+  if cfg.launch_wow.order == launch::WowOrder::BeforeImorph {
+    launch_wow(cfg, wine_prefix, db, vars)?;
+  }
+  let proc = process::Process::spawn(cmd, options.mode)
+    .context("Failed to run command")
+    .exit_code(exit::Code::ChildFailed)?;
+  run_commands_for_trigger(cfg, "after_launch", vars).await;
+  // iMorph is running by now, so a failed launch must not leave it behind
+  if cfg.launch_wow.order == launch::WowOrder::AfterImorph
+    && let Err(e) = launch_wow(cfg, wine_prefix, db, vars)
+  {
+    warn!(error = %format!("{:#}", e), "Could not launch WoW");
+  }
+
+  let hooks = async {
+    while let Some(event) = events_rx.recv().await {
+      let mut vars = vars.clone();
+      vars.extend(event.vars);
+      run_commands_for_trigger(cfg, &event.name, &vars).await;
+    }
+  };
+  let (exit_code, ()) = tokio::join!(process::run_process(proc, &options, on_line), hooks);
+  let exit_code = exit_code
+    .context("Failed to run command")
+    .exit_code(exit::Code::ChildFailed)?;
+
+  info!(exit_code, "iMorph exited");
+  let mut vars = vars.clone();
+  vars.insert("exit_code".to_string(), exit_code.to_string());
+  run_commands_for_trigger(cfg, "child_exit", &vars).await;
+
+  Ok(exit_code)
+}
+
+/// Starts WoW's executable, `{wow_exe}`, on its own unless it is already
+/// running or `[launch_wow]` is disabled
+fn launch_wow(
+  cfg: &config::Config,
+  wine_prefix: Option<&Path>,
+  db: Option<&productdb::Database>,
+  vars: &HashMap<String, String>,
+) -> Result<()> {
+  if !cfg.launch_wow.enabled {
+    return Ok(());
+  }
+
+  let exe = PathBuf::from(vars.get("wow_exe").map(String::as_str).unwrap_or_default());
+  if let Some(running) = find_running_wow(&exe, wine_prefix, db) {
+    info!(exe = %exe.display(), running, "WoW is already running");
+    return Ok(());
+  }
+
+  let args: Vec<String> = cfg
+    .launch_wow
+    .args
+    .iter()
+    .map(|arg| template::render(arg, vars))
+    .collect();
+  info!(path = %exe.display(), args = ?args, "Launching WoW");
+
+  let mut cmd = if cfg!(windows) {
+    std::process::Command::new(&exe)
+  } else {
+    wine::command(&cfg.wine, wine_prefix, &exe)
+  };
+  cmd.args(&args);
+  if let Some(dir) = exe.parent() {
+    cmd.current_dir(dir);
+  }
+
+  let pid = process::spawn_detached(cmd)
+    .with_context(|| format!("Failed to launch WoW from {:?}", exe))
+    .exit_code(exit::Code::ChildFailed)?;
+  info!(pid, "Launched WoW");
   Ok(())
 }
 
-async fn run(cfg: &config::Config) -> Result<()> {
+/// Describes the running WoW process started from `exe`, if there is one.
+/// Battle.net lists the games it started in `product.db`; other instances
+/// are found in the process table. Only the full path counts since several
+/// installs share executable names such as `WowClassic.exe`.
+fn find_running_wow(
+  exe: &Path,
+  wine_prefix: Option<&Path>,
+  db: Option<&productdb::Database>,
+) -> Option<String> {
+  // The list of running games changes, so the run's copy is too old
+  if let Some(Ok(db)) = db.map(productdb::Database::reload)
+    && let Some(active) = db.pdb.active_processes.iter().find(|p| {
+      p.process_name.contains(['/', '\\'])
+        && process::table::same_path(&db.location.install_path(&p.process_name), exe)
+    })
+  {
+    return Some(format!("pid {} according to Battle.net", active.pid));
+  }
+
+  match process::table::find_exe(exe, wine_prefix) {
+    Ok(pids) => pids.first().map(|pid| format!("pid {}", pid)),
+    Err(e) => {
+      warn!(error = %e, "Could not list running processes");
+      None
+    },
+  }
+}
+
+/// Runs iMorph, restarting it after non-zero exits when supervising is
+/// enabled. Returns the exit code of the last launch.
+async fn supervise_imorph(
+  output_dir: &Path,
+  cfg: &config::Config,
+  wine_prefix: Option<&Path>,
+  db: Option<&productdb::Database>,
+  vars: &HashMap<String, String>,
+) -> Result<u32> {
+  let mut supervisor = supervise::Supervisor::new(&cfg.supervise);
+
+  loop {
+    let exit_code = run_imorph(output_dir, cfg, wine_prefix, db, vars).await?;
+    if !cfg.supervise.enabled || exit_code == 0 {
+      return Ok(exit_code);
+    }
+
+    let delay = match supervisor.on_failure() {
+      supervise::Decision::Restart(delay) => delay,
+      supervise::Decision::Stop(reason) => {
+        error!(reason, "Not restarting iMorph");
+        history::record(history::Event::GaveUp {
+          restarts: supervisor.restarts(),
+          exit_code,
+          reason,
+        });
+        return Ok(exit_code);
+      },
+    };
+
+    let reason = format!("exited with code {}", exit_code);
+    warn!(
+      attempt = supervisor.restarts(),
+      max_restarts = cfg.supervise.max_restarts,
+      delay = ?delay,
+      "iMorph {}, restarting",
+      reason
+    );
+    history::record(history::Event::Restart {
+      attempt: supervisor.restarts(),
+      exit_code,
+      reason,
+      delay_secs: delay.as_secs(),
+    });
+
+    tokio::select! {
+      _ = tokio::time::sleep(delay) => {},
+      _ = process::interrupted() => return Err(process::Interrupted.into()),
+    }
+  }
+}
+
+/// What a successful run did
+struct Outcome {
+  /// Whether a new iMorph was downloaded
+  updated: bool,
+  /// iMorph's exit code
+  imorph_exit_code: u32,
+}
+
+/// Updates and runs iMorph. `vars` collects what is known about the run for
+/// the hooks.
+async fn run(cfg: &config::Config, vars: &mut HashMap<String, String>) -> Result<Outcome> {
   setup_environment()?;
 
   let mega_folder = cfg.mega_folder.clone();
@@ -380,91 +1206,403 @@ async fn run(cfg: &config::Config) -> Result<()> {
   ensure_output_directory(&cfg.output_directory).await?;
   let output_dir = Path::new(&cfg.output_directory);
   let version_path = output_dir.join("latest.txt");
-  let buildinfo = get_wow_build_info(cfg.product).await?;
-  let cmd_path = output_dir.join("RuniMorph.exe");
-
-  let (downloaded_imorph_version, downloaded_wow_version) =
-    read_version_file(&version_path).await?;
-
-  let mh = mh_handle.await??;
-  let entry = find_latest_imorph_entry(
-    &mh,
-    cfg.region,
-    cfg.product,
-    cfg.feature,
-    &buildinfo.version,
+  let products = product::ProductTable::with_overrides(&cfg.product_info);
+  // product.db is read once; only the steps that wait for Battle.net read it
+  // again
+  let db = match productdb::Database::read(cfg.wine.prefix().as_deref()) {
+    Ok(db) => {
+      info!(path = %db.location.path.display(), "Read Battle.net product database");
+      Some(db)
+    },
+    Err(e) => {
+      warn!(error = %e, "Could not read Battle.net product database");
+      None
+    },
+  };
+  let product =
+    resolve_product(&products, &cfg.product, db.as_ref()).exit_code(exit::Code::WowNotFound)?;
+  let region = resolve_region(cfg.region, product, db.as_ref());
+  let db = wait_for_battlenet_update(&cfg.wait_for_update, product, db).await?;
+  let wow = get_wow_build_info(
+    product,
+    region,
+    cfg.wow_install_path.as_deref().map(Path::new),
+    db.as_ref(),
+    cfg.version_source,
   )
-  .await?;
-
-  if is_already_downloaded(
-    &downloaded_imorph_version,
-    &downloaded_wow_version,
-    &entry,
-    &buildinfo,
-  ) {
+  .await
+  .exit_code(exit::Code::WowNotFound)?;
+  let wine_prefix = resolve_wine_prefix(cfg, &wow.path, db.as_ref());
+
+  vars.extend([
+    ("product".to_string(), product.code.clone()),
+    ("region".to_string(), region.to_string()),
+    ("wow_path".to_string(), wow.path.display().to_string()),
+    (
+      "wow_exe".to_string(),
+      wow
+        .path
+        .join(&product.install_folder)
+        .join(&product.executable)
+        .display()
+        .to_string(),
+    ),
+    ("wow_version".to_string(), wow.version.clone()),
+    ("imorph_path".to_string(), output_dir.display().to_string()),
+  ]);
+
+  let downloaded = read_version_file(&version_path).await?;
+  if !downloaded.wow_version.is_empty() && downloaded.wow_version != wow.version {
+    info!(
+      previous = downloaded.wow_version,
+      current = wow.version,
+      "WoW was updated since the last iMorph download"
+    );
+    let mut vars = vars.clone();
+    vars.insert(
+      "previous_wow_version".to_string(),
+      downloaded.wow_version.clone(),
+    );
+    run_commands_for_trigger(cfg, "wow_updated", &vars).await;
+  }
+
+  let mh = mh_handle.await?.exit_code(exit::Code::Network)?;
+  run_commands_for_trigger(cfg, "before_check", vars).await;
+  let entry = find_latest_imorph_entry(&mh, region, product, &cfg.feature, &wow.version).await;
+  let entry = match entry {
+    Ok(entry) => entry,
+    Err(e) => {
+      if exit::Code::for_error(&e) == exit::Code::NoRelease {
+        run_commands_for_trigger(cfg, "no_release", vars).await;
+      }
+      return Err(e);
+    },
+  };
+  vars.insert("feature".to_string(), entry.feature.to_string());
+  vars.insert("imorph_version".to_string(), entry.imorph_version.clone());
+
+  let updated = !is_already_downloaded(&downloaded, &entry, &wow.version);
+  if !updated {
     info!(
-      imorph_version = downloaded_imorph_version,
-      wow_version = downloaded_wow_version,
+      imorph_version = downloaded.imorph_version,
+      wow_version = downloaded.wow_version,
+      feature = downloaded.feature,
       "Already have the latest iMorph that targets this WoW version"
     );
-    run_imorph(output_dir, &cmd_path)?;
-    return Ok(());
+  } else {
+    run_commands_for_trigger(cfg, "update_available", vars).await;
+
+    run_required_commands_for_trigger(cfg, "before_download", vars)
+      .await
+      .context("Download rejected by a before_download command")
+      .exit_code(exit::Code::HookRejected)?;
+    let download_path = download_imorph(&mh, &entry, output_dir).await?;
+    vars.insert("zip_path".to_string(), download_path.display().to_string());
+    run_commands_for_trigger(cfg, "after_download", vars).await;
+
+    extract_imorph(&download_path, output_dir)?;
+    update_version_file(&version_path, &entry).await?;
+    prune_cache(output_dir, &entry).await;
+    run_commands_for_trigger(cfg, "after_extract", vars).await;
   }
 
-  let (res1, res2) = tokio::join!(
-    download_and_extract_imorph(&mh, &entry, output_dir),
-    update_version_file(&version_path, &entry)
+  // Pre-fetching runs while iMorph does and is abandoned once iMorph exits
+  let (done_tx, done_rx) = tokio::sync::oneshot::channel::<()>();
+  let (imorph_exit_code, ()) = tokio::join!(
+    async {
+      let exit_code =
+        supervise_imorph(output_dir, cfg, wine_prefix.as_deref(), db.as_ref(), vars).await;
+      let _ = done_tx.send(());
+      exit_code
+    },
+    async {
+      tokio::select! {
+        _ = prefetch_imorph(&mh, cfg, region, product, &wow.version, db.as_ref()) => {},
+        _ = done_rx => {},
+      }
+    },
   );
-  res1?;
-  res2?;
-  run_imorph(output_dir, &cmd_path)?;
+  let imorph_exit_code = imorph_exit_code?;
 
-  Ok(())
+  Ok(Outcome {
+    updated,
+    imorph_exit_code,
+  })
+}
+
+/// Lets a command started with `wait_for_exit = false` finish on its own.
+/// Its exit is logged, and it is killed after `timeout`, as long as the
+/// runner is still running.
+fn run_in_background(mut child: tokio::process::Child, path: String, timeout: Option<Duration>) {
+  info!(
+    command = path,
+    pid = child.id(),
+    "Not waiting for command to exit"
+  );
+
+  tokio::spawn(async move {
+    let status = match timeout {
+      Some(timeout) => {
+        match tokio::time::timeout(timeout, child.wait()).await {
+          Ok(status) => status,
+          Err(_) => {
+            warn!(command = path, timeout = ?timeout, "Command timed out, killing it");
+            let _ = child.start_kill();
+            child.wait().await
+          },
+        }
+      },
+      None => child.wait().await,
+    };
+    match status {
+      Ok(status) => info!(command = path, exit_code = status.code(), "Command exited"),
+      Err(e) => warn!(command = path, error = %e, "Command failed"),
+    }
+  });
 }
 
-/// Runs all commands configured for a given trigger
-async fn run_commands_for_trigger(cfg: &config::Config, trigger: &str) {
-  let commands = cfg.commands_for_trigger(trigger);
+/// Runs the `[[cmd]]` entries for `trigger`, logging failures. See
+/// `run_required_commands_for_trigger`.
+async fn run_commands_for_trigger(
+  cfg: &config::Config,
+  trigger: &str,
+  vars: &HashMap<String, String>,
+) {
+  if let Err(e) = run_required_commands_for_trigger(cfg, trigger, vars).await {
+    error!(trigger = trigger, error = %e, "Required command failed");
+  }
+}
+
+/// Runs the `[[cmd]]` entries for `trigger` whose `when` conditions match.
+/// `vars` and the trigger are the hook's context: they fill `{name}`
+/// placeholders in the path, arguments and cwd, are exported as
+/// `IMORPH_RUNNER_<NAME>` environment variables and are written to the
+/// stdin of `pipe` commands as a line of JSON. Stops at the first `required` command that fails and
+/// returns its error.
+async fn run_required_commands_for_trigger(
+  cfg: &config::Config,
+  trigger: &str,
+  vars: &HashMap<String, String>,
+) -> Result<()> {
+  let commands: Vec<_> = cfg
+    .commands_for_trigger(trigger)
+    .into_iter()
+    .filter(|cmd| cmd.when.matches(vars))
+    .collect();
 
   if commands.is_empty() {
-    return;
+    return Ok(());
   }
 
+  let mut context: BTreeMap<&str, &str> =
+    vars.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+  context.insert("trigger", trigger);
+  let json = serde_json::to_string(&context).unwrap_or_default();
+  let vars: HashMap<String, String> = context
+    .iter()
+    .map(|(k, v)| (k.to_string(), v.to_string()))
+    .collect();
+
   for cmd in commands {
-    let args: Vec<&str> = cmd.args.iter().map(|s| s.as_str()).collect();
+    if let Some(action) = cmd.action {
+      info!(
+        trigger = trigger,
+        action = %action,
+        required = cmd.required,
+        "Running action"
+      );
+
+      let result = match cmd.timeout() {
+        Some(timeout) => {
+          tokio::time::timeout(timeout, action::run(action, cmd, &vars))
+            .await
+            .unwrap_or_else(|_| Err(anyhow!("{} timed out after {:?}", action, timeout)))
+        },
+        None => action::run(action, cmd, &vars).await,
+      };
+      if let Err(e) = result {
+        if cmd.required {
+          return Err(e);
+        }
+        warn!(action = %action, trigger = trigger, error = %e, "Action failed");
+      }
+      continue;
+    }
+
+    let path = template::render(&cmd.path, &vars);
+    let args: Vec<String> = cmd
+      .args
+      .iter()
+      .map(|arg| template::render(arg, &vars))
+      .collect();
     let args_str = format!("{:?}", args);
+    let cwd = cmd
+      .cwd
+      .as_deref()
+      .map(|cwd| template::render(cwd, &vars))
+      .unwrap_or_else(|| ".".to_string());
 
     info!(
       trigger = trigger,
-      command = cmd.path,
+      command = path,
       args = %args_str,
+      cwd = cwd,
+      required = cmd.required,
       "Running command",
     );
 
-    if let Err(e) = pty::run_command(".", &cmd.path, &args, "[cmd] ") {
-      error!(
-        command = cmd.path,
+    let mut command = std::process::Command::new(&path);
+    command.args(&args).current_dir(&cwd);
+    for (key, value) in &context {
+      command.env(format!("IMORPH_RUNNER_{}", key.to_uppercase()), value);
+    }
+
+    let transcript = format!("cmd-{}", trigger);
+    let result = match (cmd.mode.process_mode(), cmd.wait_for_exit) {
+      (None, _) => {
+        process::spawn_detached(command).map(|pid| info!(pid, "Started detached command"))
+      },
+      (Some(mode), true) => {
+        let options = process::RunOptions {
+          mode,
+          log_prefix: "[cmd] ",
+          timeout: cmd.timeout(),
+          shutdown_timeout: cfg.shutdown_timeout(),
+          transcript: Some(&transcript),
+          stdin: Some(json.as_bytes()),
+        };
+        match process::run(command, &options).await {
+          Ok(0) => Ok(()),
+          Ok(exit_code) => Err(anyhow!("{} exited with code {}", path, exit_code)),
+          Err(e) => Err(e),
+        }
+      },
+      // Runs on its own with pipes, so it neither competes with iMorph for
+      // the console nor dies with the runner
+      (Some(_), false) => {
+        let output = match Transcript::create(&transcript) {
+          Ok(transcript) => {
+            info!(path = %transcript.path().display(), "Writing transcript");
+            Some(transcript.into_file())
+          },
+          Err(e) => {
+            warn!(error = %e, "Failed to create transcript");
+            None
+          },
+        };
+        process::spawn_background(command, Some(json.as_bytes()), output)
+          .map(|child| run_in_background(child, path.clone(), cmd.timeout()))
+      },
+    };
+    let result = result.with_context(|| format!("Failed to run {}", path));
+    if let Err(e) = result {
+      if cmd.required {
+        return Err(e);
+      }
+      warn!(
+        command = path,
         trigger = trigger,
         error = %e,
-        "Failed to run command"
+        "Command failed"
       );
     }
   }
+
+  Ok(())
 }
 
+const USAGE: &str = "Usage: imorph-runner [productdb [--json] [PATH]]
+
+Updates iMorph for the installed WoW version and runs it, as configured in
+config.toml next to the runner.
+
+  productdb   Shows what Battle.net's product.db records. See
+              imorph-runner productdb --help.
+  -h, --help  Prints this help.";
+
 #[tokio::main]
 async fn main() {
-  // Load config first so we have it available for error handling
   let cfg_file = "config.toml";
+
+  let args: Vec<String> = std::env::args().skip(1).collect();
+  match args.first().map(String::as_str) {
+    None => {},
+    Some("productdb") => {
+      // The config is only needed for the Wine prefix when no path is given
+      let wine_prefix = || config::load_or_default(cfg_file).wine.prefix();
+      let code = match productdb::report::run(&args[1..], wine_prefix) {
+        Ok(()) => 0,
+        Err(e) => {
+          eprintln!("Error: {:#}", e);
+          exit::Code::Error as i32
+        },
+      };
+      std::process::exit(code);
+    },
+    Some("-h" | "--help") => {
+      println!("{}", USAGE);
+      std::process::exit(0);
+    },
+    Some(arg) => {
+      eprintln!("Error: unknown argument {}\n\n{}", arg, USAGE);
+      std::process::exit(exit::Code::Error as i32);
+    },
+  }
+
+  // Load config first so we have it available for error handling
   let cfg = config::load_or_default(cfg_file);
 
-  match run(&cfg).await {
-    Ok(_) => {
-      run_commands_for_trigger(&cfg, "after_success").await;
+  let mut vars = HashMap::new();
+  // Ctrl-C is forwarded to a running child. Any other time it stops the run.
+  let result = tokio::select! {
+    result = run(&cfg, &mut vars) => result,
+    () = process::interrupted_outside_child() => Err(process::Interrupted.into()),
+  };
+
+  let (code, trigger) = match result {
+    Ok(outcome) if outcome.imorph_exit_code != 0 => {
+      let code = exit::Code::for_child(outcome.imorph_exit_code);
+      error!(
+        exit_code = outcome.imorph_exit_code,
+        runner_exit_code = code,
+        "iMorph failed"
+      );
+      vars.insert("exit_code".to_string(), code.to_string());
+      vars.insert(
+        "imorph_exit_code".to_string(),
+        outcome.imorph_exit_code.to_string(),
+      );
+      vars.insert(
+        "error".to_string(),
+        format!("iMorph exited with code {}", outcome.imorph_exit_code),
+      );
+      (code, "after_error")
+    },
+    Ok(outcome) => {
+      let code = if outcome.updated {
+        exit::Code::Updated
+      } else {
+        exit::Code::UpToDate
+      } as i32;
+      vars.insert("exit_code".to_string(), code.to_string());
+      (code, "after_success")
     },
     Err(e) => {
-      error!("{}", e);
-      run_commands_for_trigger(&cfg, "after_error").await;
+      error!("{:#}", e);
+      let code = exit::Code::for_error(&e) as i32;
+      vars.insert("exit_code".to_string(), code.to_string());
+      vars.insert("error".to_string(), format!("{:#}", e));
+      (code, "after_error")
     },
   };
+
+  tokio::select! {
+    () = run_commands_for_trigger(&cfg, trigger, &vars) => {},
+    () = process::interrupted_outside_child() => {
+      warn!("Interrupted, skipping the remaining {} commands", trigger);
+      std::process::exit(exit::Code::Interrupted as i32);
+    },
+  }
+
+  std::process::exit(code);
 }
@@ -5,6 +5,7 @@ use anyhow::anyhow;
 use megalib::Node;
 use megalib::PublicFolder;
 use regex::Regex;
+use tracing::warn;
 
 pub struct MegaHelper {
   folder: PublicFolder,
@@ -19,21 +20,15 @@ impl MegaHelper {
     })
   }
 
+  /// Lists every iMorph release (all feature variants) for the region, product
+  /// folder and WoW version. Files that are not iMorph releases, or are
+  /// variants this version does not know, are skipped with a warning.
   pub async fn fetch_entries(
     &self,
     region: crate::Region,
-    product: crate::Product,
-    feature: crate::Feature,
+    product_folder: &str,
     wow_version: &str,
   ) -> Result<Vec<crate::ImorphEntry>> {
-    let product_path = match product {
-      crate::Product::WoW => "retail",
-      crate::Product::WoWClassic => "classic",
-      crate::Product::WoWClassicEra => "cata",
-      crate::Product::WoWBeta => "beta",
-      crate::Product::WoWXPtr => "xptr",
-    };
-
     // Get root folder
     let root = self
       .folder
@@ -41,7 +36,7 @@ impl MegaHelper {
       .first()
       .ok_or_else(|| anyhow!("Unable to find root in public folder."))?;
     let root_path = root.path().unwrap_or("/");
-    let path = format!("{}/{}", root_path, product_path);
+    let path = format!("{}/{}", root_path, product_folder);
 
     let app_regex = Regex::new(r"iMorph-([\d\.]+)(\((.*?)\))?\[(China)? ?([\d\.]+)\].zip")?;
     let mut all_downloads = vec![];
@@ -50,7 +45,8 @@ impl MegaHelper {
     for node in self.folder.list(&path, false) {
       let line = node.name.clone();
       let Some(caps) = app_regex.captures(&line) else {
-        return Err(anyhow!("regex failed to match iMorph line: {}", line));
+        warn!(file = line, "Skipping file that is not an iMorph release");
+        continue;
       };
 
       let (Some(imorph_version), entry_feature, entry_region, Some(entry_wow_version)) = (
@@ -59,23 +55,35 @@ impl MegaHelper {
         caps.get(4).map(|v| v.as_str()),
         caps.get(5).map(|v| v.as_str()),
       ) else {
-        return Err(anyhow!("unexpected iMorph line: {}", line));
+        warn!(file = line, "Skipping file that is not an iMorph release");
+        continue;
       };
 
-      let entry_feature: crate::Feature = entry_feature.unwrap_or("").parse()?;
-      let entry_region: crate::Region = entry_region.unwrap_or("").parse()?;
+      let entry_feature: crate::Feature = match entry_feature.unwrap_or("").parse() {
+        Ok(feature) => feature,
+        Err(e) => {
+          warn!(file = line, error = %e, "Skipping iMorph release of an unknown variant");
+          continue;
+        },
+      };
+      let entry_region: crate::Region = match entry_region.unwrap_or("").parse() {
+        Ok(region) => region,
+        Err(e) => {
+          warn!(file = line, error = %e, "Skipping iMorph release of an unknown region");
+          continue;
+        },
+      };
 
-      if feature != entry_feature || entry_region != region || entry_wow_version != wow_version {
+      if entry_region != region || entry_wow_version != wow_version {
         continue;
       }
 
       all_downloads.push(crate::ImorphEntry {
-        // feature: entry_feature,
+        feature: entry_feature,
         imorph_version: imorph_version.to_string(),
         wow_version: entry_wow_version.to_string(),
         node: node.clone(),
         // region: entry_region,
-        // product,
       });
     }
 
@@ -0,0 +1,101 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+use anyhow::Result;
+use regex::Regex;
+use serde::Deserialize;
+use tracing::Level;
+
+/// Turns matching lines of iMorph's output into a named event
+#[derive(Debug, Deserialize, Clone)]
+pub struct OutputRuleConfig {
+  /// Name of the event. `[[cmd]]` entries with this trigger are run.
+  pub event: String,
+  /// Regular expression matched against each line with colors removed
+  pub pattern: String,
+  /// Logs the matching line at this level ("error", "warn", "info", ...)
+  pub level: Option<String>,
+  /// Kills iMorph when the line matches
+  #[serde(default)]
+  pub terminate: bool,
+}
+
+/// An output rule matching a line
+#[derive(Debug)]
+pub struct Event {
+  pub name: String,
+  /// The line, the event name and the pattern's captures by name and index
+  pub vars: HashMap<String, String>,
+  pub level: Option<Level>,
+  pub terminate: bool,
+}
+
+struct OutputRule {
+  config: OutputRuleConfig,
+  regex: Regex,
+  level: Option<Level>,
+}
+
+/// The configured output rules, ready for matching
+pub struct OutputRules {
+  rules: Vec<OutputRule>,
+}
+
+impl OutputRules {
+  pub fn compile(configs: &[OutputRuleConfig]) -> Result<Self> {
+    let rules = configs
+      .iter()
+      .map(|config| {
+        let regex = Regex::new(&config.pattern)
+          .with_context(|| format!("Invalid pattern for output rule \"{}\"", config.event))?;
+        let level = config
+          .level
+          .as_deref()
+          .map(str::parse)
+          .transpose()
+          .with_context(|| format!("Invalid level for output rule \"{}\"", config.event))?;
+
+        Ok(OutputRule {
+          config: config.clone(),
+          regex,
+          level,
+        })
+      })
+      .collect::<Result<_>>()?;
+
+    Ok(Self {
+      rules,
+    })
+  }
+
+  /// Returns an event for every rule matching `line`
+  pub fn matches(&self, line: &str) -> Vec<Event> {
+    self
+      .rules
+      .iter()
+      .filter_map(|rule| {
+        let caps = rule.regex.captures(line)?;
+
+        let mut vars = HashMap::new();
+        vars.insert("line".to_string(), line.to_string());
+        vars.insert("event".to_string(), rule.config.event.clone());
+        for (i, name) in rule.regex.capture_names().enumerate() {
+          let Some(value) = caps.get(i) else {
+            continue;
+          };
+          vars.insert(i.to_string(), value.as_str().to_string());
+          if let Some(name) = name {
+            vars.insert(name.to_string(), value.as_str().to_string());
+          }
+        }
+
+        Some(Event {
+          name: rule.config.event.clone(),
+          vars,
+          level: rule.level,
+          terminate: rule.config.terminate,
+        })
+      })
+      .collect()
+  }
+}
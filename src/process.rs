@@ -0,0 +1,797 @@
+use std::fmt;
+use std::io::Read;
+use std::io::Write;
+use std::process::Command;
+use std::process::ExitStatus;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::sync::LazyLock;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc as std_mpsc;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Result;
+use anyhow::anyhow;
+use pty::PtyBackend;
+use regex::Regex;
+use serde::Deserialize;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Notify;
+use tokio::sync::mpsc;
+use tokio::sync::oneshot;
+use tracing::Level;
+use tracing::debug;
+use tracing::error;
+use tracing::info;
+use tracing::trace;
+use tracing::warn;
+
+use crate::console;
+use crate::transcript::Transcript;
+
+mod pty;
+pub mod table;
+
+/// How often blocking backends check whether the child is still alive
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long output is still read after the child exited
+const OUTPUT_GRACE: Duration = Duration::from_secs(1);
+
+/// Target of the log events for child output. The terminal shows the child's
+/// own colored output instead of these events.
+pub const OUTPUT_TARGET: &str = "output";
+
+/// How a child's standard streams are connected
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum Mode {
+  /// A pseudo terminal, so the child behaves as if it was run from a console
+  #[default]
+  #[serde(rename = "pty")]
+  Pty,
+  /// Plain pipes for stdout and stderr
+  #[serde(rename = "pipe")]
+  Pipe,
+  /// The runner's own console. No output is captured.
+  #[serde(rename = "inherit")]
+  Inherit,
+}
+
+/// Returned by `run` when the user interrupted the child with Ctrl-C
+#[derive(Debug)]
+pub struct Interrupted;
+
+impl fmt::Display for Interrupted {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "interrupted by the user")
+  }
+}
+
+impl std::error::Error for Interrupted {}
+
+/// How `run` runs a command
+#[derive(Debug, Clone)]
+pub struct RunOptions<'a> {
+  pub mode: Mode,
+  /// Prepended to each logged line of output
+  pub log_prefix: &'a str,
+  /// Kills the child if it runs longer than this
+  pub timeout: Option<Duration>,
+  /// How long to wait for the child to exit after forwarding Ctrl-C
+  pub shutdown_timeout: Duration,
+  /// Writes the output to a transcript with this name
+  pub transcript: Option<&'a str>,
+  /// Data for the child's stdin, which pipe children read to end of stream.
+  /// Other children do not get it, since a terminal would take it as typed
+  /// input.
+  pub stdin: Option<&'a [u8]>,
+}
+
+impl Default for RunOptions<'_> {
+  fn default() -> Self {
+    Self {
+      mode: Mode::Pty,
+      log_prefix: "",
+      timeout: None,
+      shutdown_timeout: Duration::from_secs(10),
+      transcript: None,
+      stdin: None,
+    }
+  }
+}
+
+/// A line of child output
+#[derive(Debug, Clone)]
+pub struct Line {
+  /// The line as the child printed it, colors included, without the line
+  /// ending
+  pub raw: String,
+  /// The line with escape sequences and the line ending removed
+  pub text: String,
+}
+
+/// What `run_with` does with a line of output
+#[derive(Debug, Clone, Copy)]
+pub struct LineAction {
+  /// The level the line is logged at
+  pub level: Level,
+  /// Kills the child
+  pub kill: bool,
+}
+
+impl Default for LineAction {
+  fn default() -> Self {
+    Self {
+      level: Level::INFO,
+      kill: false,
+    }
+  }
+}
+
+fn strip_osc(s: &str) -> String {
+  // OSC sequences: ESC ] number ; text BEL (or ESC \)
+  static OSC_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\x1b\][0-9]+;[^\x07\x1b]*(\x07|\x1b\\)").unwrap());
+  OSC_RE.replace_all(s, "").to_string()
+}
+
+fn strip_ansi(s: &str) -> String {
+  static ANSI_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\x1b\[([\x30-\x3f]*[\x20-\x2f]*[\x40-\x7e])").unwrap());
+  ANSI_RE.replace_all(s, "").to_string()
+}
+
+/// Splits raw output into `Line`s and sends them to the output stream
+struct LineSplitter {
+  buffer: Vec<u8>,
+  tx: mpsc::UnboundedSender<Line>,
+}
+
+impl LineSplitter {
+  fn new(tx: mpsc::UnboundedSender<Line>) -> Self {
+    Self {
+      buffer: Vec::new(),
+      tx,
+    }
+  }
+
+  fn push(&mut self, data: &[u8]) {
+    for &byte in data {
+      self.buffer.push(byte);
+      if byte == b'\n' {
+        self.send();
+      }
+    }
+  }
+
+  /// Sends whatever is left after the last newline
+  fn flush(&mut self) {
+    if !self.buffer.is_empty() {
+      self.send();
+    }
+  }
+
+  fn send(&mut self) {
+    let raw = strip_osc(&String::from_utf8_lossy(&self.buffer))
+      .trim_end_matches(['\r', '\n'])
+      .to_string();
+    let text = strip_ansi(&raw);
+    let _ = self.tx.send(Line {
+      raw,
+      text,
+    });
+    self.buffer.clear();
+  }
+}
+
+/// Requests termination of a running child
+#[derive(Debug, Clone, Default)]
+pub struct Killer {
+  requested: Arc<AtomicBool>,
+  notify: Arc<Notify>,
+}
+
+impl Killer {
+  pub fn kill(&self) {
+    self.requested.store(true, Ordering::SeqCst);
+    self.notify.notify_one();
+  }
+
+  fn is_requested(&self) -> bool {
+    self.requested.load(Ordering::SeqCst)
+  }
+
+  async fn requested(&self) {
+    if !self.is_requested() {
+      self.notify.notified().await;
+    }
+  }
+}
+
+/// Keyboard input for a pseudo terminal, shared by the console forwarder and
+/// `Process::interrupt`
+type SharedInput = Arc<Mutex<Box<dyn Write + Send>>>;
+
+/// A running child process
+pub struct Process {
+  output: mpsc::UnboundedReceiver<Line>,
+  exit: oneshot::Receiver<Result<u32>>,
+  killer: Killer,
+  input: Option<SharedInput>,
+}
+
+impl Process {
+  /// Spawns `cmd` with its streams connected according to `mode`
+  pub fn spawn(cmd: Command, mode: Mode) -> Result<Self> {
+    Self::spawn_with_stdin(cmd, mode, None)
+  }
+
+  /// Like `spawn`, and writes `stdin` to the child's input when it is a pipe.
+  /// See `RunOptions::stdin`.
+  pub fn spawn_with_stdin(cmd: Command, mode: Mode, stdin: Option<&[u8]>) -> Result<Self> {
+    let (tx, output) = mpsc::unbounded_channel();
+    let (exit_tx, exit) = oneshot::channel();
+    let killer = Killer::default();
+
+    let input = match mode {
+      Mode::Pty => Some(spawn_pty(cmd, tx, exit_tx, killer.clone())?),
+      Mode::Pipe => {
+        spawn_child(cmd, mode, stdin, tx, exit_tx, killer.clone())?;
+        None
+      },
+      Mode::Inherit => {
+        spawn_child(cmd, mode, None, tx, exit_tx, killer.clone())?;
+        None
+      },
+    };
+
+    Ok(Self {
+      output,
+      exit,
+      killer,
+      input,
+    })
+  }
+
+  /// Takes the stream of output lines. It ends once the child's output is
+  /// closed. Later calls return an already closed stream.
+  pub fn take_output(&mut self) -> mpsc::UnboundedReceiver<Line> {
+    let (_, closed) = mpsc::unbounded_channel();
+    std::mem::replace(&mut self.output, closed)
+  }
+
+  pub fn kill(&self) {
+    self.killer.kill();
+  }
+
+  /// Forwards Ctrl-C to the child. Only pseudo terminal children need this:
+  /// pipe and inherit children share the runner's console and receive Ctrl-C
+  /// directly.
+  pub fn interrupt(&self) {
+    if let Some(input) = &self.input {
+      let sent = input
+        .lock()
+        .map(|mut input| input.write_all(b"\x03").and_then(|_| input.flush()).is_ok())
+        .unwrap_or(false);
+      if !sent {
+        warn!("Failed to forward Ctrl-C, killing process");
+        self.kill();
+      }
+    }
+  }
+
+  /// Waits for the child to exit and returns its exit code
+  pub async fn wait(&mut self) -> Result<u32> {
+    (&mut self.exit)
+      .await
+      .map_err(|_| anyhow!("process runner stopped unexpectedly"))?
+  }
+
+  /// Waits up to `timeout` for the child to exit, killing it if it does not
+  pub async fn wait_timeout(&mut self, timeout: Duration) -> Result<u32> {
+    match tokio::time::timeout(timeout, self.wait()).await {
+      Ok(result) => result,
+      Err(_) => {
+        warn!(timeout = ?timeout, "Process timed out, killing it");
+        self.kill();
+        self.wait().await?;
+        Err(anyhow!("process timed out after {:?}", timeout))
+      },
+    }
+  }
+}
+
+/// Starts `cmd` on its own, without the runner's console, and returns its
+/// process id. The child is not waited for and outlives the runner.
+pub fn spawn_detached(mut cmd: Command) -> Result<u32> {
+  cmd
+    .stdin(Stdio::null())
+    .stdout(Stdio::null())
+    .stderr(Stdio::null());
+  detach(&mut cmd);
+
+  let child = cmd.spawn()?;
+  Ok(child.id())
+}
+
+/// Starts `cmd` on its own like `spawn_detached`, but writes `stdin` to its
+/// input and sends its output to `output`. The child outlives the runner;
+/// the returned handle only lets the runner wait for it while it runs.
+pub fn spawn_background(
+  mut cmd: Command,
+  stdin: Option<&[u8]>,
+  output: Option<std::fs::File>,
+) -> Result<tokio::process::Child> {
+  let (stdout, stderr) = match output {
+    Some(file) => (Stdio::from(file.try_clone()?), Stdio::from(file)),
+    None => (Stdio::null(), Stdio::null()),
+  };
+  cmd
+    .stdin(if stdin.is_some() {
+      Stdio::piped()
+    } else {
+      Stdio::null()
+    })
+    .stdout(stdout)
+    .stderr(stderr);
+  detach(&mut cmd);
+
+  let mut child = tokio::process::Command::from(cmd).spawn()?;
+  write_stdin(&mut child, stdin);
+
+  Ok(child)
+}
+
+/// Writes `stdin` to the child's input in the background when it has a pipe
+fn write_stdin(child: &mut tokio::process::Child, stdin: Option<&[u8]>) {
+  if let (Some(mut input), Some(data)) = (child.stdin.take(), stdin) {
+    let data = data.to_vec();
+    tokio::spawn(async move {
+      // Dropping the pipe afterwards closes the child's stdin
+      if let Err(e) = input.write_all(&data).await {
+        warn!(error = %e, "Failed to write to process input");
+      }
+    });
+  }
+}
+
+/// Keeps a child away from the runner's console
+fn detach(cmd: &mut Command) {
+  #[cfg(windows)]
+  {
+    use std::os::windows::process::CommandExt;
+
+    use windows::Win32::System::Threading::CREATE_NEW_PROCESS_GROUP;
+    use windows::Win32::System::Threading::DETACHED_PROCESS;
+    cmd.creation_flags((DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP).0);
+  }
+
+  // A process group of its own keeps Ctrl-C in the runner's terminal from
+  // reaching it
+  #[cfg(unix)]
+  std::os::unix::process::CommandExt::process_group(cmd, 0);
+}
+
+/// Number of children `run_process` is currently forwarding Ctrl-C to
+static FORWARDING: AtomicUsize = AtomicUsize::new(0);
+
+/// Counts a child in `FORWARDING` while it is alive
+struct ForwardingGuard;
+
+impl ForwardingGuard {
+  fn new() -> Self {
+    FORWARDING.fetch_add(1, Ordering::SeqCst);
+    Self
+  }
+}
+
+impl Drop for ForwardingGuard {
+  fn drop(&mut self) {
+    FORWARDING.fetch_sub(1, Ordering::SeqCst);
+  }
+}
+
+/// Resolves when the user presses Ctrl-C while no child is running to
+/// forward it to
+pub async fn interrupted_outside_child() {
+  loop {
+    interrupted().await;
+    if FORWARDING.load(Ordering::SeqCst) == 0 {
+      return;
+    }
+  }
+}
+
+/// Resolves when the user presses Ctrl-C, or Ctrl-Break on Windows
+pub async fn interrupted() {
+  #[cfg(windows)]
+  {
+    let Ok(mut ctrl_break) = tokio::signal::windows::ctrl_break() else {
+      return std::future::pending().await;
+    };
+    tokio::select! {
+      res = tokio::signal::ctrl_c() => {
+        if res.is_err() {
+          ctrl_break.recv().await;
+        }
+      },
+      _ = ctrl_break.recv() => {},
+    }
+  }
+
+  #[cfg(not(windows))]
+  if tokio::signal::ctrl_c().await.is_err() {
+    std::future::pending::<()>().await;
+  }
+}
+
+/// Runs `cmd` to completion, logging each line of output after the prefix.
+/// Ctrl-C is forwarded to the child, which gets `shutdown_timeout` to exit
+/// before it is killed; a second Ctrl-C kills it right away. `Interrupted` is
+/// returned in either case.
+pub async fn run(cmd: Command, options: &RunOptions<'_>) -> Result<u32> {
+  run_with(cmd, options, |_| LineAction::default()).await
+}
+
+/// Like `run`, but `on_line` sees each line first and decides how it is
+/// logged and whether the child is killed
+pub async fn run_with(
+  cmd: Command,
+  options: &RunOptions<'_>,
+  on_line: impl FnMut(&Line) -> LineAction,
+) -> Result<u32> {
+  let proc = Process::spawn_with_stdin(cmd, options.mode, options.stdin)?;
+  run_process(proc, options, on_line).await
+}
+
+/// Like `run_with`, for a child that was already spawned
+pub async fn run_process(
+  mut proc: Process,
+  options: &RunOptions<'_>,
+  mut on_line: impl FnMut(&Line) -> LineAction,
+) -> Result<u32> {
+  let _forwarding = ForwardingGuard::new();
+  let mut output = proc.take_output();
+  let killer = proc.killer.clone();
+  let mut transcript = options.transcript.and_then(|name| {
+    match Transcript::create(name) {
+      Ok(transcript) => {
+        info!(path = %transcript.path().display(), "Writing transcript");
+        Some(transcript)
+      },
+      Err(e) => {
+        warn!(error = %e, "Failed to create transcript");
+        None
+      },
+    }
+  });
+
+  let log = async {
+    while let Some(line) = output.recv().await {
+      let action = on_line(&line);
+      if let Some(transcript) = &mut transcript {
+        transcript.write_line(&line.text);
+      }
+      log_line(action.level, options.log_prefix, &line);
+      if action.kill && !killer.is_requested() {
+        warn!("Killing process");
+        killer.kill();
+      }
+    }
+  };
+  let wait = async {
+    tokio::select! {
+      exit_code = async {
+        match options.timeout {
+          Some(timeout) => proc.wait_timeout(timeout).await,
+          None => proc.wait().await,
+        }
+      } => return exit_code,
+      _ = interrupted() => {},
+    }
+
+    info!(
+      timeout = ?options.shutdown_timeout,
+      "Forwarding Ctrl-C and waiting for the process to exit"
+    );
+    proc.interrupt();
+    tokio::select! {
+      res = proc.wait_timeout(options.shutdown_timeout) => {
+        res?;
+      },
+      _ = interrupted() => {
+        warn!("Interrupted again, killing process");
+        proc.kill();
+        proc.wait().await?;
+      },
+    }
+
+    Err(Interrupted.into())
+  };
+
+  let mut log = std::pin::pin!(log);
+  let mut wait = std::pin::pin!(wait);
+  let (exit_code, logged) = tokio::select! {
+    exit_code = &mut wait => (exit_code, false),
+    () = &mut log => ((&mut wait).await, true),
+  };
+  // Processes the child started can hold its output open after it exits
+  if !logged && tokio::time::timeout(OUTPUT_GRACE, log).await.is_err() {
+    debug!("Stopped reading output that outlived the process");
+  }
+  exit_code
+}
+
+/// Logs a line of child output. Lines the terminal filter lets through are
+/// also printed to the terminal as they are, colors included.
+fn log_line(level: Level, prefix: &str, line: &Line) {
+  let text = &line.text;
+  match level {
+    Level::ERROR => error!(target: OUTPUT_TARGET, "{}{}", prefix, text),
+    Level::WARN => warn!(target: OUTPUT_TARGET, "{}{}", prefix, text),
+    Level::INFO => info!(target: OUTPUT_TARGET, "{}{}", prefix, text),
+    Level::DEBUG => debug!(target: OUTPUT_TARGET, "{}{}", prefix, text),
+    _ => trace!(target: OUTPUT_TARGET, "{}{}", prefix, text),
+  }
+
+  if level <= Level::INFO {
+    // Reset colors so they do not bleed into the runner's own output
+    println!("{}{}\x1b[0m", prefix, line.raw);
+  }
+}
+
+fn exit_code(status: ExitStatus) -> u32 {
+  status.code().map(|c| c as u32).unwrap_or(1)
+}
+
+/// Spawns a thread that forwards everything read from `reader` to a channel.
+/// `None` is sent at end of stream.
+fn spawn_reader(mut reader: impl Read + Send + 'static) -> std_mpsc::Receiver<Option<Vec<u8>>> {
+  let (tx, rx) = std_mpsc::channel();
+
+  thread::spawn(move || {
+    let mut buf = [0u8; 1024];
+    loop {
+      match reader.read(&mut buf) {
+        Ok(0) => {
+          // End of stream
+          let _ = tx.send(None);
+          break;
+        },
+        Ok(n) => {
+          let _ = tx.send(Some(buf[..n].to_vec()));
+        },
+        Err(_) => {
+          let _ = tx.send(None);
+          break;
+        },
+      };
+    }
+  });
+
+  rx
+}
+
+/// Forwards lines typed into the runner's console to a pseudo terminal until
+/// `exited` is set
+fn spawn_input_forwarder(input: SharedInput, exited: Arc<AtomicBool>) {
+  thread::spawn(move || {
+    while !exited.load(Ordering::SeqCst) {
+      let line = match console::read_line_timeout(POLL_INTERVAL) {
+        Ok(line) => line,
+        Err(std_mpsc::RecvTimeoutError::Timeout) => continue,
+        Err(std_mpsc::RecvTimeoutError::Disconnected) => break,
+      };
+
+      // Terminals expect a carriage return for Enter
+      let line = format!("{}\r", line.trim_end_matches(['\r', '\n']));
+      let Ok(mut input) = input.lock() else {
+        break;
+      };
+      if input
+        .write_all(line.as_bytes())
+        .and_then(|_| input.flush())
+        .is_err()
+      {
+        break;
+      }
+    }
+  });
+}
+
+/// Runs the child in a pseudo terminal. The backend is blocking, so a thread
+/// owns it and polls for output, exit and kill requests. Returns the
+/// terminal's input.
+fn spawn_pty(
+  cmd: Command,
+  tx: mpsc::UnboundedSender<Line>,
+  exit_tx: oneshot::Sender<Result<u32>>,
+  killer: Killer,
+) -> Result<SharedInput> {
+  let mut proc = pty::Backend::spawn(cmd)?;
+  let rx = spawn_reader(proc.output()?);
+  let input: SharedInput = Arc::new(Mutex::new(proc.input()?));
+  let exited = Arc::new(AtomicBool::new(false));
+  spawn_input_forwarder(input.clone(), exited.clone());
+
+  thread::spawn(move || {
+    let mut killed = false;
+    let mut kill_if_requested = |proc: &mut pty::Backend| {
+      if !killed && killer.is_requested() {
+        killed = true;
+        if let Err(e) = proc.kill() {
+          warn!(error = %e, "Failed to kill process");
+        }
+      }
+    };
+
+    let mut lines = LineSplitter::new(tx);
+    loop {
+      kill_if_requested(&mut proc);
+      match rx.recv_timeout(POLL_INTERVAL) {
+        Ok(Some(data)) => lines.push(&data),
+        Ok(None) => break, // Reader thread signaled EOF
+        Err(std_mpsc::RecvTimeoutError::Timeout) => {
+          if !proc.is_alive() {
+            // No more data coming, process is dead
+            break;
+          }
+        },
+        Err(_) => break, // Channel disconnected
+      }
+    }
+    lines.flush();
+    drop(lines);
+
+    // The terminal can close before the child exits
+    while proc.is_alive() {
+      kill_if_requested(&mut proc);
+      thread::sleep(POLL_INTERVAL);
+    }
+
+    let _ = exit_tx.send(proc.wait());
+    exited.store(true, Ordering::SeqCst);
+  });
+
+  Ok(input)
+}
+
+/// Runs the child with pipes or the runner's own console
+fn spawn_child(
+  mut cmd: Command,
+  mode: Mode,
+  stdin: Option<&[u8]>,
+  tx: mpsc::UnboundedSender<Line>,
+  exit_tx: oneshot::Sender<Result<u32>>,
+  killer: Killer,
+) -> Result<()> {
+  if mode == Mode::Pipe {
+    cmd
+      .stdin(if stdin.is_some() {
+        Stdio::piped()
+      } else {
+        Stdio::null()
+      })
+      .stdout(Stdio::piped())
+      .stderr(Stdio::piped());
+  }
+
+  let mut child = tokio::process::Command::from(cmd).spawn()?;
+
+  write_stdin(&mut child, stdin);
+
+  if let Some(stdout) = child.stdout.take() {
+    tokio::spawn(read_lines(stdout, tx.clone()));
+  }
+  if let Some(stderr) = child.stderr.take() {
+    tokio::spawn(read_lines(stderr, tx.clone()));
+  }
+  drop(tx);
+
+  tokio::spawn(async move {
+    let status = tokio::select! {
+      status = child.wait() => status,
+      _ = killer.requested() => {
+        if let Err(e) = child.start_kill() {
+          warn!(error = %e, "Failed to kill process");
+        }
+        child.wait().await
+      },
+    };
+
+    let _ = exit_tx.send(status.map(exit_code).map_err(anyhow::Error::from));
+  });
+
+  Ok(())
+}
+
+async fn read_lines(mut reader: impl AsyncRead + Unpin, tx: mpsc::UnboundedSender<Line>) {
+  let mut lines = LineSplitter::new(tx);
+  let mut buf = [0u8; 1024];
+
+  loop {
+    match reader.read(&mut buf).await {
+      Ok(0) | Err(_) => break,
+      Ok(n) => lines.push(&buf[..n]),
+    }
+  }
+
+  lines.flush();
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use std::time::Instant;
+
+  use super::*;
+
+  fn sh(script: &str) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.args(["-c", script]);
+    cmd
+  }
+
+  /// Runs `script` and returns its exit code and the lines it printed
+  async fn run_script(script: &str, mode: Mode) -> (Result<u32>, Vec<Line>) {
+    let options = RunOptions {
+      mode,
+      ..Default::default()
+    };
+    let mut lines = vec![];
+    let exit_code = run_with(sh(script), &options, |line| {
+      lines.push(line.clone());
+      LineAction::default()
+    })
+    .await;
+    (exit_code, lines)
+  }
+
+  async fn strips_colors_and_returns_exit_code(mode: Mode) {
+    let (exit_code, lines) =
+      run_script("printf '\\033[31mred\\033[0m text\\n'; exit 3", mode).await;
+
+    assert_eq!(exit_code.unwrap(), 3);
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0].text, "red text");
+    assert_eq!(lines[0].raw, "\x1b[31mred\x1b[0m text");
+  }
+
+  async fn kills_child_after_timeout(mode: Mode) {
+    let options = RunOptions {
+      mode,
+      timeout: Some(Duration::from_millis(300)),
+      ..Default::default()
+    };
+    let start = Instant::now();
+    let exit_code = run(sh("sleep 10"), &options).await;
+
+    assert!(exit_code.unwrap_err().to_string().contains("timed out"));
+    assert!(start.elapsed() < Duration::from_secs(5));
+  }
+
+  #[tokio::test]
+  async fn pty_strips_colors_and_returns_exit_code() {
+    strips_colors_and_returns_exit_code(Mode::Pty).await;
+  }
+
+  #[tokio::test]
+  async fn pipe_strips_colors_and_returns_exit_code() {
+    strips_colors_and_returns_exit_code(Mode::Pipe).await;
+  }
+
+  #[tokio::test]
+  async fn pty_kills_child_after_timeout() {
+    kills_child_after_timeout(Mode::Pty).await;
+  }
+
+  #[tokio::test]
+  async fn pipe_kills_child_after_timeout() {
+    kills_child_after_timeout(Mode::Pipe).await;
+  }
+}
@@ -0,0 +1,255 @@
+use std::io::Read;
+use std::io::Write;
+use std::process::Command;
+
+/// A child process attached to a pseudo terminal
+pub trait PtyBackend: Sized {
+  /// Spawns `cmd` with its standard streams attached to a new pseudo terminal
+  fn spawn(cmd: Command) -> anyhow::Result<Self>;
+
+  /// Returns a reader for everything the child writes to the terminal
+  fn output(&mut self) -> anyhow::Result<Box<dyn Read + Send>>;
+
+  /// Returns a writer for sending keyboard input to the terminal
+  fn input(&mut self) -> anyhow::Result<Box<dyn Write + Send>>;
+
+  fn is_alive(&mut self) -> bool;
+
+  /// Terminates the child immediately
+  fn kill(&mut self) -> anyhow::Result<()>;
+
+  /// Waits for the child to exit and returns its exit code
+  fn wait(&mut self) -> anyhow::Result<u32>;
+}
+
+/// The pseudo terminal implementation for this platform
+#[cfg(windows)]
+pub type Backend = ConPty;
+
+/// The pseudo terminal implementation for this platform
+#[cfg(unix)]
+pub type Backend = UnixPty;
+
+/// Adds the runner's environment variables to `cmd` unless it sets or
+/// removes them itself. conpty gives the child only the variables set on
+/// the command once there are any, so without this a child with one extra
+/// variable would lose `PATH`, `SystemRoot` and the rest. Names are compared
+/// without case, like Windows does.
+#[cfg(any(windows, test))]
+fn inherit_env(cmd: &mut Command) {
+  let explicit: Vec<std::ffi::OsString> = cmd
+    .get_envs()
+    .map(|(key, _)| key.to_ascii_uppercase())
+    .collect();
+  if explicit.is_empty() {
+    return;
+  }
+
+  for (key, value) in std::env::vars_os() {
+    if !explicit.contains(&key.to_ascii_uppercase()) {
+      cmd.env(key, value);
+    }
+  }
+}
+
+/// Windows pseudo console
+#[cfg(windows)]
+pub struct ConPty(conpty::Process);
+
+#[cfg(windows)]
+impl PtyBackend for ConPty {
+  fn spawn(mut cmd: Command) -> anyhow::Result<Self> {
+    inherit_env(&mut cmd);
+    Ok(Self(conpty::Process::spawn(cmd)?))
+  }
+
+  fn output(&mut self) -> anyhow::Result<Box<dyn Read + Send>> {
+    Ok(Box::new(self.0.output()?))
+  }
+
+  fn input(&mut self) -> anyhow::Result<Box<dyn Write + Send>> {
+    Ok(Box::new(self.0.input()?))
+  }
+
+  fn is_alive(&mut self) -> bool {
+    self.0.is_alive()
+  }
+
+  fn kill(&mut self) -> anyhow::Result<()> {
+    Ok(self.0.exit(1)?)
+  }
+
+  fn wait(&mut self) -> anyhow::Result<u32> {
+    Ok(self.0.wait(None)?)
+  }
+}
+
+/// Unix pseudo terminal from `openpty`, with the child in its own session
+#[cfg(unix)]
+pub struct UnixPty {
+  child: std::process::Child,
+  master: Option<std::fs::File>,
+}
+
+#[cfg(unix)]
+impl UnixPty {
+  fn master(&self) -> anyhow::Result<&std::fs::File> {
+    self
+      .master
+      .as_ref()
+      .ok_or_else(|| anyhow::anyhow!("pseudo terminal is closed"))
+  }
+
+  /// The size of the runner's own terminal, or 80x24 when it has none
+  fn window_size() -> libc::winsize {
+    let mut size = libc::winsize {
+      ws_row: 24,
+      ws_col: 80,
+      ws_xpixel: 0,
+      ws_ypixel: 0,
+    };
+
+    // SAFETY: TIOCGWINSZ only writes to `size`, which lives for the call.
+    unsafe {
+      let mut current = size;
+      if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut current) == 0
+        && current.ws_row > 0
+        && current.ws_col > 0
+      {
+        size = current;
+      }
+    }
+
+    size
+  }
+}
+
+#[cfg(unix)]
+impl PtyBackend for UnixPty {
+  fn spawn(mut cmd: Command) -> anyhow::Result<Self> {
+    use std::fs::File;
+    use std::io;
+    use std::os::fd::AsRawFd;
+    use std::os::fd::FromRawFd;
+    use std::os::fd::OwnedFd;
+    use std::os::unix::process::CommandExt;
+    use std::process::Stdio;
+
+    let mut master = -1;
+    let mut slave = -1;
+    let size = Self::window_size();
+
+    // SAFETY: openpty writes two new descriptors that we take ownership of
+    // immediately.
+    let (master, slave) = unsafe {
+      if libc::openpty(
+        &mut master,
+        &mut slave,
+        std::ptr::null_mut(),
+        std::ptr::null(),
+        &size,
+      ) != 0
+      {
+        return Err(io::Error::last_os_error().into());
+      }
+      (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave))
+    };
+
+    // The runner's console already echoes what is typed, so turn echo off.
+    // SAFETY: `termios` is fully written by tcgetattr before it is used.
+    unsafe {
+      let mut termios = std::mem::zeroed::<libc::termios>();
+      if libc::tcgetattr(slave.as_raw_fd(), &mut termios) == 0 {
+        termios.c_lflag &= !libc::ECHO;
+        libc::tcsetattr(slave.as_raw_fd(), libc::TCSANOW, &termios);
+      }
+    }
+
+    cmd.stdin(Stdio::from(slave.try_clone()?));
+    cmd.stdout(Stdio::from(slave.try_clone()?));
+    cmd.stderr(Stdio::from(slave));
+
+    // SAFETY: only async-signal-safe calls are made between fork and exec.
+    unsafe {
+      cmd.pre_exec(|| {
+        // Start a new session so the terminal becomes the controlling one
+        if libc::setsid() == -1 {
+          return Err(io::Error::last_os_error());
+        }
+        if libc::ioctl(0, libc::TIOCSCTTY as _, 0) == -1 {
+          return Err(io::Error::last_os_error());
+        }
+        Ok(())
+      });
+    }
+
+    let child = cmd.spawn()?;
+    // The parent's copies of the slave end were moved into `cmd` and are
+    // closed when it is dropped, so reads see EOF/EIO once the child exits.
+    drop(cmd);
+
+    Ok(Self {
+      child,
+      master: Some(File::from(master)),
+    })
+  }
+
+  fn output(&mut self) -> anyhow::Result<Box<dyn Read + Send>> {
+    Ok(Box::new(self.master()?.try_clone()?))
+  }
+
+  fn input(&mut self) -> anyhow::Result<Box<dyn Write + Send>> {
+    Ok(Box::new(self.master()?.try_clone()?))
+  }
+
+  fn is_alive(&mut self) -> bool {
+    matches!(self.child.try_wait(), Ok(None))
+  }
+
+  fn kill(&mut self) -> anyhow::Result<()> {
+    Ok(self.child.kill()?)
+  }
+
+  fn wait(&mut self) -> anyhow::Result<u32> {
+    let status = self.child.wait()?;
+    self.master = None;
+    Ok(status.code().map(|c| c as u32).unwrap_or(1))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn inherits_env_next_to_explicit_variables() {
+    let path = std::env::var_os("PATH").expect("PATH is set");
+    let mut cmd = Command::new("child");
+    cmd.env("IMORPH_RUNNER_TEST", "1");
+    cmd.env_remove("HOME");
+    inherit_env(&mut cmd);
+
+    let envs: Vec<_> = cmd.get_envs().collect();
+    assert!(envs.contains(&("PATH".as_ref(), Some(path.as_os_str()))));
+    assert!(envs.contains(&("IMORPH_RUNNER_TEST".as_ref(), Some("1".as_ref()))));
+    assert!(envs.contains(&("HOME".as_ref(), None)));
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn pty_child_sees_path() {
+    let mut cmd = Command::new("sh");
+    cmd.args(["-c", "printf %s \"$PATH\""]);
+    cmd.env("IMORPH_RUNNER_TEST", "1");
+
+    let mut pty = Backend::spawn(cmd).unwrap();
+    let mut output = pty.output().unwrap();
+    assert_eq!(pty.wait().unwrap(), 0);
+
+    let mut seen = vec![];
+    // Reading fails with EIO instead of EOF once the child is gone
+    let _ = output.read_to_end(&mut seen);
+    let path = std::env::var("PATH").unwrap();
+    assert_eq!(String::from_utf8_lossy(&seen), path);
+  }
+}
@@ -0,0 +1,218 @@
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use anyhow::Result;
+use anyhow::anyhow;
+use tracing::info;
+use tracing::warn;
+
+/// Whether the executable name `exe` is the process called `name`. The
+/// `.exe` extension and any leading folders are optional and case is
+/// ignored.
+pub fn is_named(exe: &str, name: &str) -> bool {
+  let strip = |s: &str| {
+    let s = s
+      .rsplit(['/', '\\'])
+      .next()
+      .unwrap_or_default()
+      .to_lowercase();
+    s.strip_suffix(".exe").map(str::to_string).unwrap_or(s)
+  };
+  strip(exe) == strip(name)
+}
+
+/// Finds the ids of running processes called `name`
+#[cfg(windows)]
+pub fn find(name: &str) -> Result<Vec<u32>> {
+  use windows::Win32::Foundation::CloseHandle;
+  use windows::Win32::System::Diagnostics::ToolHelp::CreateToolhelp32Snapshot;
+  use windows::Win32::System::Diagnostics::ToolHelp::PROCESSENTRY32W;
+  use windows::Win32::System::Diagnostics::ToolHelp::Process32FirstW;
+  use windows::Win32::System::Diagnostics::ToolHelp::Process32NextW;
+  use windows::Win32::System::Diagnostics::ToolHelp::TH32CS_SNAPPROCESS;
+
+  let mut pids = vec![];
+  unsafe {
+    let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0)?;
+    let mut entry = PROCESSENTRY32W {
+      dwSize: std::mem::size_of::<PROCESSENTRY32W>() as u32,
+      ..Default::default()
+    };
+
+    let mut found = Process32FirstW(snapshot, &mut entry).is_ok();
+    while found {
+      let len = entry
+        .szExeFile
+        .iter()
+        .position(|&c| c == 0)
+        .unwrap_or(entry.szExeFile.len());
+      let exe = String::from_utf16_lossy(&entry.szExeFile[..len]);
+      if is_named(&exe, name) && entry.th32ProcessID != std::process::id() {
+        pids.push(entry.th32ProcessID);
+      }
+      found = Process32NextW(snapshot, &mut entry).is_ok();
+    }
+
+    let _ = CloseHandle(snapshot);
+  }
+
+  Ok(pids)
+}
+
+/// Finds the ids of running processes called `name` in `/proc`. Processes
+/// started through Wine are found by the Windows executable in their command
+/// line.
+#[cfg(unix)]
+pub fn find(name: &str) -> Result<Vec<u32>> {
+  let mut pids = vec![];
+
+  for entry in std::fs::read_dir("/proc")?.filter_map(|e| e.ok()) {
+    let Some(pid) = entry
+      .file_name()
+      .to_str()
+      .and_then(|s| s.parse::<u32>().ok())
+    else {
+      continue;
+    };
+    if pid == std::process::id() {
+      continue;
+    }
+
+    let comm = std::fs::read_to_string(entry.path().join("comm")).unwrap_or_default();
+    let cmdline = std::fs::read(entry.path().join("cmdline")).unwrap_or_default();
+    let exe = cmdline
+      .split(|&b| b == 0)
+      .next()
+      .map(String::from_utf8_lossy)
+      .unwrap_or_default();
+
+    if is_named(comm.trim_end(), name) || is_named(&exe, name) {
+      pids.push(pid);
+    }
+  }
+
+  Ok(pids)
+}
+
+/// Full path of the executable of the process with id `pid`, if it can be
+/// read
+#[cfg(windows)]
+pub fn image_path(pid: u32, _wine_prefix: Option<&Path>) -> Option<PathBuf> {
+  use windows::Win32::Foundation::CloseHandle;
+  use windows::Win32::System::Threading::OpenProcess;
+  use windows::Win32::System::Threading::PROCESS_NAME_WIN32;
+  use windows::Win32::System::Threading::PROCESS_QUERY_LIMITED_INFORMATION;
+  use windows::Win32::System::Threading::QueryFullProcessImageNameW;
+  use windows::core::PWSTR;
+
+  let mut buf = [0u16; 1024];
+  let mut len = buf.len() as u32;
+  unsafe {
+    let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid).ok()?;
+    let result = QueryFullProcessImageNameW(
+      process,
+      PROCESS_NAME_WIN32,
+      PWSTR(buf.as_mut_ptr()),
+      &mut len,
+    );
+    let _ = CloseHandle(process);
+    result.ok()?;
+  }
+
+  Some(PathBuf::from(String::from_utf16_lossy(
+    &buf[..len as usize],
+  )))
+}
+
+/// Full path of the executable of the process with id `pid`, if it can be
+/// read. Wine processes have the Windows path of their executable as the
+/// first word of their command line, which is mapped into `wine_prefix`.
+#[cfg(unix)]
+pub fn image_path(pid: u32, wine_prefix: Option<&Path>) -> Option<PathBuf> {
+  let proc = PathBuf::from("/proc").join(pid.to_string());
+  let cmdline = std::fs::read(proc.join("cmdline")).ok()?;
+  let first = cmdline
+    .split(|&b| b == 0)
+    .next()
+    .map(String::from_utf8_lossy)
+    .unwrap_or_default();
+
+  let is_windows_path = first.len() > 2 && first.as_bytes()[1] == b':';
+  match wine_prefix {
+    Some(prefix) if is_windows_path => Some(crate::wine::to_host_path(prefix, &first)),
+    _ if is_windows_path => None,
+    _ => std::fs::read_link(proc.join("exe")).ok(),
+  }
+}
+
+/// Whether `a` and `b` are the same file. Paths are resolved when they exist
+/// and compared without case, like Windows and Wine do.
+pub fn same_path(a: &Path, b: &Path) -> bool {
+  let resolve = |p: &Path| {
+    std::fs::canonicalize(p)
+      .unwrap_or_else(|_| p.to_path_buf())
+      .to_string_lossy()
+      .replace('\\', "/")
+  };
+  resolve(a).eq_ignore_ascii_case(&resolve(b))
+}
+
+/// Finds the ids of running processes started from the executable `exe`.
+/// Processes with the same name started from elsewhere, e.g. another WoW
+/// install, are left out.
+pub fn find_exe(exe: &Path, wine_prefix: Option<&Path>) -> Result<Vec<u32>> {
+  let name = exe.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+  Ok(
+    find(name)?
+      .into_iter()
+      .filter(|&pid| image_path(pid, wine_prefix).is_some_and(|path| same_path(&path, exe)))
+      .collect(),
+  )
+}
+
+/// Kills the process with id `pid`
+#[cfg(windows)]
+pub fn kill(pid: u32) -> Result<()> {
+  use windows::Win32::Foundation::CloseHandle;
+  use windows::Win32::System::Threading::OpenProcess;
+  use windows::Win32::System::Threading::PROCESS_TERMINATE;
+  use windows::Win32::System::Threading::TerminateProcess;
+
+  unsafe {
+    let process = OpenProcess(PROCESS_TERMINATE, false, pid)
+      .with_context(|| format!("Failed to open process {}", pid))?;
+    let result = TerminateProcess(process, 1);
+    let _ = CloseHandle(process);
+    result.with_context(|| format!("Failed to kill process {}", pid))
+  }
+}
+
+/// Kills the process with id `pid`
+#[cfg(unix)]
+pub fn kill(pid: u32) -> Result<()> {
+  if unsafe { libc::kill(pid as i32, libc::SIGTERM) } != 0 {
+    return Err(std::io::Error::last_os_error())
+      .with_context(|| format!("Failed to kill process {}", pid));
+  }
+  Ok(())
+}
+
+/// Kills every process in `pids`, carrying on past the ones that cannot be
+/// killed, e.g. because access is denied or they already exited. Fails only
+/// when none of them could be killed.
+pub fn kill_all(pids: &[u32]) -> Result<()> {
+  let mut errors = vec![];
+  for &pid in pids {
+    info!(pid, "Killing process");
+    if let Err(e) = kill(pid) {
+      warn!(error = %e, "Could not kill process");
+      errors.push(format!("{:#}", e));
+    }
+  }
+
+  if !pids.is_empty() && errors.len() == pids.len() {
+    return Err(anyhow!("Could not kill any process: {}", errors.join("; ")));
+  }
+  Ok(())
+}
@@ -0,0 +1,109 @@
+use serde::Deserialize;
+
+/// Value of `product` that picks from the installed products at startup
+pub const AUTO: &str = "auto";
+
+/// Describes a WoW flavor Battle.net can install
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProductInfo {
+  /// Battle.net product code, as used in `product.db` and the `product` setting
+  pub code: String,
+  /// Human readable name shown in menus. Defaults to the code.
+  #[serde(default)]
+  pub name: String,
+  /// Value of the `Product` column in `.build.info`. Defaults to the code.
+  #[serde(default)]
+  pub build_info_product: String,
+  /// Folder holding this product's releases in the iMorph mega folder
+  pub folder: String,
+  /// Folder of this flavor in the WoW install, e.g. `_retail_`
+  #[serde(default)]
+  pub install_folder: String,
+  /// The game's executable in `install_folder`, e.g. `Wow.exe`
+  #[serde(default)]
+  pub executable: String,
+}
+
+impl ProductInfo {
+  fn new(code: &str, name: &str, folder: &str, install_folder: &str, executable: &str) -> Self {
+    Self {
+      code: code.to_string(),
+      name: name.to_string(),
+      build_info_product: code.to_string(),
+      folder: folder.to_string(),
+      install_folder: install_folder.to_string(),
+      executable: executable.to_string(),
+    }
+  }
+}
+
+/// The known products: the built-in WoW flavors plus any defined in config
+#[derive(Debug, Clone)]
+pub struct ProductTable {
+  products: Vec<ProductInfo>,
+}
+
+impl Default for ProductTable {
+  fn default() -> Self {
+    Self {
+      products: vec![
+        ProductInfo::new("wow", "Retail", "retail", "_retail_", "Wow.exe"),
+        ProductInfo::new("wowxptr", "Retail XPTR", "xptr", "_xptr_", "WowT.exe"),
+        ProductInfo::new("wow_beta", "Retail Beta", "beta", "_beta_", "WowB.exe"),
+        ProductInfo::new(
+          "wow_classic",
+          "Classic",
+          "classic",
+          "_classic_",
+          "WowClassic.exe",
+        ),
+        ProductInfo::new(
+          "wow_classic_era",
+          "Classic Era",
+          "cata",
+          "_classic_era_",
+          "WowClassic.exe",
+        ),
+      ],
+    }
+  }
+}
+
+impl ProductTable {
+  /// Builds the table from the built-in products, letting `overrides` replace
+  /// built-in entries with the same code or add new ones
+  pub fn with_overrides(overrides: &[ProductInfo]) -> Self {
+    let mut table = Self::default();
+
+    for info in overrides {
+      let mut info = info.clone();
+      if info.name.is_empty() {
+        info.name = info.code.clone();
+      }
+      if info.build_info_product.is_empty() {
+        info.build_info_product = info.code.clone();
+      }
+
+      match table.products.iter_mut().find(|p| p.code == info.code) {
+        Some(existing) => {
+          // Keep what the built-in entry knows about the install
+          if info.install_folder.is_empty() {
+            info.install_folder = existing.install_folder.clone();
+          }
+          if info.executable.is_empty() {
+            info.executable = existing.executable.clone();
+          }
+          *existing = info;
+        },
+        None => table.products.push(info),
+      }
+    }
+
+    table
+  }
+
+  /// Finds a product by its Battle.net product code
+  pub fn get(&self, code: &str) -> Option<&ProductInfo> {
+    self.products.iter().find(|p| p.code == code)
+  }
+}
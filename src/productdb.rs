@@ -1,14 +1,413 @@
 pub mod proto {
+  #[allow(clippy::enum_variant_names)]
   pub mod defs {
     include!(concat!(env!("OUT_DIR"), "/productdb.defs.rs"));
   }
 }
 
+pub mod report;
+
+use std::cmp::Ordering;
+use std::env;
+use std::fmt;
+use std::fs;
 use std::io::Cursor;
+use std::path::Path;
+use std::path::PathBuf;
 
+use anyhow::Result;
+use anyhow::anyhow;
 use prost::Message;
 pub use proto::defs;
+use serde::Deserialize;
 
 pub fn deserialize(buf: &[u8]) -> Result<defs::ProductDb, prost::DecodeError> {
   defs::ProductDb::decode(&mut Cursor::new(buf))
 }
+
+/// A product database and the Wine prefix it was found in, if any
+#[derive(Debug, Clone)]
+pub struct Location {
+  pub path: PathBuf,
+  pub wine_prefix: Option<PathBuf>,
+}
+
+impl Location {
+  /// Maps an install path recorded in the database to a local path
+  pub fn install_path(&self, path: &str) -> PathBuf {
+    match &self.wine_prefix {
+      Some(prefix) => crate::wine::to_host_path(prefix, path),
+      None => PathBuf::from(path),
+    }
+  }
+}
+
+/// Path of the product database relative to `ProgramData`
+fn relative_path() -> PathBuf {
+  PathBuf::from("Battle.net").join("Agent").join("product.db")
+}
+
+/// Finds the Battle.net agent's product database. It is read from
+/// `wine_prefix` when given, from `PROGRAMDATA` on Windows, and otherwise
+/// from the first discovered Wine prefix that has one.
+pub fn locate(wine_prefix: Option<&Path>) -> Result<Location> {
+  let in_prefix = |prefix: &Path| {
+    Location {
+      path: prefix
+        .join("drive_c")
+        .join("ProgramData")
+        .join(relative_path()),
+      wine_prefix: Some(prefix.to_path_buf()),
+    }
+  };
+
+  if let Some(prefix) = wine_prefix {
+    return Ok(in_prefix(prefix));
+  }
+
+  if cfg!(windows) {
+    let pd = env::var("PROGRAMDATA")
+      .map_err(|e| anyhow!("Could not read PROGRAMDATA environment variable: {}", e))?;
+
+    return Ok(Location {
+      path: PathBuf::from(pd).join(relative_path()),
+      wine_prefix: None,
+    });
+  }
+
+  crate::wine::discover_prefixes()
+    .iter()
+    .map(|prefix| in_prefix(prefix))
+    .find(|location| location.path.exists())
+    .ok_or_else(|| anyhow!("Could not find product.db in any Wine prefix. Set [wine] prefix."))
+}
+
+/// Reads and decodes a product database file
+pub fn load(path: &Path) -> Result<defs::ProductDb> {
+  let data = fs::read(path)?;
+  Ok(deserialize(&data)?)
+}
+
+/// A product database read from disk and the location it was read from
+#[derive(Debug, Clone)]
+pub struct Database {
+  pub location: Location,
+  pub pdb: defs::ProductDb,
+}
+
+impl Database {
+  /// Finds the product database like [`locate`] does and reads it
+  pub fn read(wine_prefix: Option<&Path>) -> Result<Self> {
+    let location = locate(wine_prefix)?;
+    let pdb = load(&location.path)?;
+    Ok(Self {
+      location,
+      pdb,
+    })
+  }
+
+  /// Reads the database again from the same location, to see what
+  /// Battle.net changed since
+  pub fn reload(&self) -> Result<Self> {
+    Ok(Self {
+      location: self.location.clone(),
+      pdb: load(&self.location.path)?,
+    })
+  }
+
+  /// Finds the install entry for a Battle.net product code
+  pub fn find_install(&self, product_code: &str) -> Option<&defs::ProductInstall> {
+    find_install(&self.pdb, product_code)
+  }
+}
+
+/// Finds the install entry for a Battle.net product code
+pub fn find_install<'a>(
+  pdb: &'a defs::ProductDb,
+  product_code: &str,
+) -> Option<&'a defs::ProductInstall> {
+  pdb
+    .product_installs
+    .iter()
+    .find(|install| install.product_code == product_code)
+}
+
+/// Returns every region Battle.net reports for an install: the play region
+/// from the user settings followed by the installed build config regions
+pub fn install_regions(install: &defs::ProductInstall) -> Vec<String> {
+  let mut regions = vec![];
+
+  if let Some(settings) = &install.settings {
+    regions.push(settings.play_region.clone());
+  }
+
+  if let Some(base) = base_state(install) {
+    regions.extend(base.installed_build_config.iter().map(|c| c.region.clone()));
+  }
+
+  regions.retain(|r| !r.is_empty());
+  regions
+}
+
+/// Returns the base product state for an install, if Battle.net cached one
+pub fn base_state(install: &defs::ProductInstall) -> Option<&defs::BaseProductState> {
+  install
+    .cached_product_state
+    .as_ref()
+    .and_then(|s| s.base_product_state.as_ref())
+}
+
+/// Returns the WoW version Battle.net recorded for an install, if any
+pub fn current_version(install: &defs::ProductInstall) -> Option<&str> {
+  base_state(install)
+    .map(|base| base.current_version_str.as_str())
+    .filter(|version| !version.is_empty())
+}
+
+/// Which source of the WoW version wins when `.build.info` and `product.db`
+/// disagree
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VersionSource {
+  /// Trusts `.build.info`
+  #[default]
+  BuildInfo,
+  /// Trusts Battle.net's `product.db`
+  ProductDb,
+  /// Picks the higher of the two versions
+  Newest,
+}
+
+impl VersionSource {
+  /// Picks the version from `build_info` or `product_db`
+  pub fn pick<'a>(self, build_info: &'a str, product_db: &'a str) -> &'a str {
+    match self {
+      VersionSource::BuildInfo => build_info,
+      VersionSource::ProductDb => product_db,
+      VersionSource::Newest => {
+        if compare_versions(product_db, build_info) == Ordering::Greater {
+          product_db
+        } else {
+          build_info
+        }
+      },
+    }
+  }
+}
+
+impl fmt::Display for VersionSource {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      VersionSource::BuildInfo => write!(f, "build_info"),
+      VersionSource::ProductDb => write!(f, "product_db"),
+      VersionSource::Newest => write!(f, "newest"),
+    }
+  }
+}
+
+/// Compares WoW versions such as `11.0.5.57388` part by part
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+  let parts = |v: &str| -> Vec<u64> { v.split('.').map(|p| p.parse().unwrap_or(0)).collect() };
+  parts(a).cmp(&parts(b))
+}
+
+/// Returns the build configs Battle.net downloads in the background ahead
+/// of a patch
+pub fn background_downloads(install: &defs::ProductInstall) -> Vec<&defs::BuildConfig> {
+  base_state(install)
+    .map(|base| {
+      base
+        .background_download_build_config
+        .iter()
+        .filter(|c| !c.build_config.is_empty())
+        .collect()
+    })
+    .unwrap_or_default()
+}
+
+/// Waiting for Battle.net to finish updating or repairing WoW before its
+/// version is read
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct WaitForUpdateConfig {
+  pub enabled: bool,
+  /// Gives up after this long
+  pub timeout_secs: u64,
+  /// How often `product.db` is read again
+  pub poll_secs: u64,
+}
+
+impl Default for WaitForUpdateConfig {
+  fn default() -> Self {
+    Self {
+      enabled: true,
+      timeout_secs: 120,
+      poll_secs: 5,
+    }
+  }
+}
+
+/// What Battle.net is doing with an install, from its cached state
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpdateStatus {
+  pub playable: bool,
+  pub update_complete: bool,
+  pub operation: defs::Operation,
+  /// Progress of the active update or repair, from 0 to 1
+  pub progress: Option<f64>,
+  /// Bytes the update still has to download
+  pub download_remaining: Option<u64>,
+}
+
+impl UpdateStatus {
+  /// Reads the status of `install`. An update or repair only counts as
+  /// active while its progress is recorded; `active_operation` alone is
+  /// left behind by operations that never started or were cancelled.
+  pub fn of(install: &defs::ProductInstall) -> Self {
+    let base = base_state(install);
+    let cached = install.cached_product_state.as_ref();
+    let operation = install
+      .product_operations
+      .as_ref()
+      .map(|ops| ops.active_operation())
+      .unwrap_or(defs::Operation::OpNone);
+
+    let (operation, progress, download_remaining) = match operation {
+      defs::Operation::OpUpdate => {
+        match cached.and_then(|c| c.update_progress.as_ref()) {
+          Some(update) => {
+            (
+              operation,
+              Some(update.progress),
+              Some(update.download_remaining),
+            )
+          },
+          None => (defs::Operation::OpNone, None, None),
+        }
+      },
+      defs::Operation::OpRepair => {
+        match cached.and_then(|c| c.repair_progress.as_ref()) {
+          Some(repair) => (operation, Some(repair.progress), None),
+          None => (defs::Operation::OpNone, None, None),
+        }
+      },
+      _ => (operation, None, None),
+    };
+
+    Self {
+      playable: base.is_some_and(|b| b.playable),
+      update_complete: base.is_some_and(|b| b.update_complete),
+      operation,
+      progress,
+      download_remaining,
+    }
+  }
+
+  /// Whether an update or repair is running. Background downloads do not
+  /// count.
+  pub fn is_updating(&self) -> bool {
+    matches!(
+      self.operation,
+      defs::Operation::OpUpdate | defs::Operation::OpRepair
+    )
+  }
+
+  /// Whether the game can be started. No update or repair may be running,
+  /// and after one was seen running the game must be playable again.
+  /// Without one `playable` is not required: it keeps its last value while
+  /// Battle.net is closed.
+  pub fn is_ready(&self, seen_update: bool) -> bool {
+    !self.is_updating() && (self.playable || !seen_update)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn install(operation: defs::Operation, update_progress: Option<f64>) -> defs::ProductInstall {
+    defs::ProductInstall {
+      product_code: "wow".to_string(),
+      cached_product_state: Some(defs::CachedProductState {
+        update_progress: update_progress.map(|progress| {
+          defs::UpdateProgress {
+            progress,
+            download_remaining: 1024,
+            ..Default::default()
+          }
+        }),
+        ..Default::default()
+      }),
+      product_operations: Some(defs::ProductOperations {
+        active_operation: operation as i32,
+        ..Default::default()
+      }),
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn update_with_progress_is_not_ready() {
+    let status = UpdateStatus::of(&install(defs::Operation::OpUpdate, Some(0.5)));
+    assert_eq!(status.operation, defs::Operation::OpUpdate);
+    assert_eq!(status.progress, Some(0.5));
+    assert_eq!(status.download_remaining, Some(1024));
+    assert!(status.is_updating());
+    assert!(!status.is_ready(false));
+  }
+
+  #[test]
+  fn update_without_progress_is_ready() {
+    // An absent active_operation decodes as OP_UPDATE
+    let status = UpdateStatus::of(&install(defs::Operation::OpUpdate, None));
+    assert_eq!(status.operation, defs::Operation::OpNone);
+    assert!(!status.is_updating());
+    assert!(status.is_ready(false));
+  }
+
+  #[test]
+  fn compares_versions_part_by_part() {
+    assert_eq!(
+      compare_versions("11.0.5.57388", "11.0.5.57388"),
+      Ordering::Equal
+    );
+    assert_eq!(compare_versions("11.0.10.1", "11.0.9.2"), Ordering::Greater);
+    assert_eq!(
+      compare_versions("1.15.4.56738", "11.0.5.57388"),
+      Ordering::Less
+    );
+    assert_eq!(compare_versions("11.0.5", "11.0.5.1"), Ordering::Less);
+  }
+
+  #[test]
+  fn version_source_picks_version() {
+    let (build_info, product_db) = ("11.0.5.57388", "11.0.7.58123");
+    assert_eq!(
+      VersionSource::BuildInfo.pick(build_info, product_db),
+      build_info
+    );
+    assert_eq!(
+      VersionSource::ProductDb.pick(build_info, product_db),
+      product_db
+    );
+    assert_eq!(
+      VersionSource::Newest.pick(build_info, product_db),
+      product_db
+    );
+    assert_eq!(
+      VersionSource::Newest.pick(product_db, build_info),
+      product_db
+    );
+  }
+
+  #[test]
+  fn waits_for_playable_after_an_update() {
+    let mut status = UpdateStatus::of(&install(defs::Operation::OpNone, None));
+    assert!(!status.playable);
+    assert!(status.is_ready(false));
+    assert!(!status.is_ready(true));
+
+    status.playable = true;
+    assert!(status.is_ready(true));
+  }
+}
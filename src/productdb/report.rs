@@ -0,0 +1,261 @@
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use anyhow::anyhow;
+use serde::Serialize;
+
+use super::UpdateStatus;
+use super::defs;
+
+const USAGE: &str = "Usage: imorph-runner productdb [--json] [PATH]
+
+Shows what Battle.net's product.db records: installs, versions, regions,
+languages, active operations and active processes.
+
+  PATH    A product.db file. Defaults to the one Battle.net uses.
+  --json  Prints JSON instead of tables.";
+
+/// Options of the `productdb` subcommand
+#[derive(Debug, Default)]
+struct Args {
+  path: Option<PathBuf>,
+  json: bool,
+  help: bool,
+}
+
+impl Args {
+  fn parse(args: &[String]) -> Result<Self> {
+    let mut parsed = Args::default();
+
+    for arg in args {
+      match arg.as_str() {
+        "--json" => parsed.json = true,
+        "-h" | "--help" => parsed.help = true,
+        flag if flag.starts_with('-') => return Err(anyhow!("unknown option {}", flag)),
+        path if parsed.path.is_none() => parsed.path = Some(PathBuf::from(path)),
+        path => return Err(anyhow!("unexpected argument {}", path)),
+      }
+    }
+
+    Ok(parsed)
+  }
+}
+
+/// Everything the subcommand shows about a product database
+#[derive(Debug, Serialize)]
+struct Report {
+  path: PathBuf,
+  installs: Vec<Install>,
+  active_processes: Vec<Process>,
+}
+
+#[derive(Debug, Serialize)]
+struct Install {
+  product_code: String,
+  uid: String,
+  install_path: String,
+  version: Option<String>,
+  play_region: String,
+  /// Regions of the installed build configs
+  regions: Vec<String>,
+  text_language: String,
+  speech_language: String,
+  languages: Vec<String>,
+  branch: String,
+  playable: bool,
+  update_complete: bool,
+  operation: &'static str,
+  /// Progress of the active update or repair, from 0 to 1
+  progress: Option<f64>,
+  background_download: bool,
+  background_download_complete: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct Process {
+  name: String,
+  pid: i32,
+  uri: Vec<String>,
+}
+
+impl Report {
+  fn new(path: &Path, pdb: &defs::ProductDb) -> Self {
+    Self {
+      path: path.to_path_buf(),
+      installs: pdb.product_installs.iter().map(Install::new).collect(),
+      active_processes: pdb
+        .active_processes
+        .iter()
+        .map(|p| {
+          Process {
+            name: p.process_name.clone(),
+            pid: p.pid,
+            uri: p.uri.clone(),
+          }
+        })
+        .collect(),
+    }
+  }
+
+  /// Writes the installs and processes as aligned tables
+  fn write_tables(&self, out: &mut impl Write) -> io::Result<()> {
+    writeln!(out, "{}", self.path.display())?;
+    writeln!(out)?;
+
+    let installs: Vec<Vec<String>> = self
+      .installs
+      .iter()
+      .map(|i| {
+        vec![
+          i.product_code.clone(),
+          i.version.clone().unwrap_or_else(|| "-".to_string()),
+          i.play_region.clone(),
+          i.regions.join(","),
+          if i.text_language.is_empty() && i.speech_language.is_empty() {
+            "-".to_string()
+          } else {
+            format!("{}/{}", i.text_language, i.speech_language)
+          },
+          match i.progress {
+            Some(progress) => format!("{} {:.0}%", i.operation, progress * 100.0),
+            None => i.operation.to_string(),
+          },
+          i.install_path.clone(),
+        ]
+      })
+      .collect();
+    write_table(
+      out,
+      &[
+        "PRODUCT",
+        "VERSION",
+        "REGION",
+        "BUILD REGIONS",
+        "TEXT/SPEECH",
+        "OPERATION",
+        "PATH",
+      ],
+      &installs,
+    )?;
+    writeln!(out)?;
+
+    let processes: Vec<Vec<String>> = self
+      .active_processes
+      .iter()
+      .map(|p| vec![p.name.clone(), p.pid.to_string(), p.uri.join(" ")])
+      .collect();
+    write_table(out, &["PROCESS", "PID", "URI"], &processes)
+  }
+}
+
+impl Install {
+  fn new(install: &defs::ProductInstall) -> Self {
+    let settings = install.settings.clone().unwrap_or_default();
+    let base = super::base_state(install);
+    let status = UpdateStatus::of(install);
+
+    Self {
+      product_code: install.product_code.clone(),
+      uid: install.uid.clone(),
+      install_path: settings.install_path,
+      version: super::current_version(install).map(str::to_string),
+      play_region: settings.play_region,
+      regions: base
+        .map(|b| {
+          b.installed_build_config
+            .iter()
+            .map(|c| c.region.clone())
+            .collect()
+        })
+        .unwrap_or_default(),
+      text_language: settings.selected_text_language,
+      speech_language: settings.selected_speech_language,
+      languages: settings
+        .languages
+        .iter()
+        .map(|l| l.language.clone())
+        .collect(),
+      branch: settings.versionbranch,
+      playable: status.playable,
+      update_complete: status.update_complete,
+      operation: operation_name(status.operation),
+      progress: status.progress,
+      background_download: base.is_some_and(|b| b.background_download_available),
+      background_download_complete: base.is_some_and(|b| b.background_download_complete),
+    }
+  }
+}
+
+fn operation_name(operation: defs::Operation) -> &'static str {
+  match operation {
+    defs::Operation::OpUpdate => "update",
+    defs::Operation::OpBackfill => "backfill",
+    defs::Operation::OpRepair => "repair",
+    defs::Operation::OpNone => "none",
+  }
+}
+
+/// Writes `rows` under `headers` with every column padded to its widest cell
+fn write_table(out: &mut impl Write, headers: &[&str], rows: &[Vec<String>]) -> io::Result<()> {
+  let mut widths: Vec<usize> = headers.iter().map(|h| h.len()).collect();
+  for row in rows {
+    for (width, cell) in widths.iter_mut().zip(row) {
+      *width = (*width).max(cell.chars().count());
+    }
+  }
+
+  let mut write_row = |cells: &[&str]| {
+    let line: Vec<String> = cells
+      .iter()
+      .zip(&widths)
+      .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+      .collect();
+    writeln!(out, "{}", line.join("  ").trim_end())
+  };
+
+  write_row(headers)?;
+  if rows.is_empty() {
+    write_row(&["(none)"])?;
+  }
+  for row in rows {
+    write_row(&row.iter().map(String::as_str).collect::<Vec<_>>())?;
+  }
+
+  Ok(())
+}
+
+/// Runs the `productdb` subcommand with the arguments after its name.
+/// Without a path the database is found like it is for a normal run, in
+/// the Wine prefix `wine_prefix` returns.
+pub fn run(args: &[String], wine_prefix: impl FnOnce() -> Option<PathBuf>) -> Result<()> {
+  let args = Args::parse(args).map_err(|e| anyhow!("{}\n\n{}", e, USAGE))?;
+  if args.help {
+    println!("{}", USAGE);
+    return Ok(());
+  }
+
+  let path = match args.path {
+    Some(path) => path,
+    None => super::locate(wine_prefix().as_deref())?.path,
+  };
+  let pdb = super::load(&path).map_err(|e| anyhow!("could not read {}: {}", path.display(), e))?;
+  let report = Report::new(&path, &pdb);
+
+  let mut out = io::stdout().lock();
+  let written = if args.json {
+    serde_json::to_writer_pretty(&mut out, &report)
+      .map_err(io::Error::from)
+      .and_then(|()| writeln!(out))
+  } else {
+    report.write_tables(&mut out)
+  };
+
+  match written {
+    // The output was piped into a program that stopped reading
+    Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+    written => Ok(written?),
+  }
+}
@@ -0,0 +1,102 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+use std::time::Instant;
+
+use serde::Deserialize;
+
+/// Restarting iMorph when it exits with a non-zero code
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct SuperviseConfig {
+  pub enabled: bool,
+  /// Gives up after this many restarts
+  pub max_restarts: u32,
+  /// Delay before the first restart. Doubled for every restart after it.
+  pub backoff_secs: u64,
+  /// Upper bound for the delay between restarts
+  pub max_backoff_secs: u64,
+  /// Gives up when iMorph exits this many times within `crash_loop_window_secs`
+  pub crash_loop_threshold: u32,
+  pub crash_loop_window_secs: u64,
+}
+
+impl Default for SuperviseConfig {
+  fn default() -> Self {
+    Self {
+      enabled: false,
+      max_restarts: 5,
+      backoff_secs: 2,
+      max_backoff_secs: 60,
+      crash_loop_threshold: 3,
+      crash_loop_window_secs: 60,
+    }
+  }
+}
+
+/// What to do after iMorph exited
+#[derive(Debug)]
+pub enum Decision {
+  /// Restart after the delay
+  Restart(Duration),
+  /// Stop supervising for the given reason
+  Stop(String),
+}
+
+/// Tracks iMorph's exits and decides whether to restart it
+pub struct Supervisor<'a> {
+  cfg: &'a SuperviseConfig,
+  restarts: u32,
+  exits: VecDeque<Instant>,
+}
+
+impl<'a> Supervisor<'a> {
+  pub fn new(cfg: &'a SuperviseConfig) -> Self {
+    Self {
+      cfg,
+      restarts: 0,
+      exits: VecDeque::new(),
+    }
+  }
+
+  /// The number of restarts so far
+  pub fn restarts(&self) -> u32 {
+    self.restarts
+  }
+
+  /// Records a non-zero exit and decides what to do next
+  pub fn on_failure(&mut self) -> Decision {
+    let now = Instant::now();
+    let window = Duration::from_secs(self.cfg.crash_loop_window_secs);
+    self.exits.push_back(now);
+    while self
+      .exits
+      .front()
+      .is_some_and(|t| now.duration_since(*t) > window)
+    {
+      self.exits.pop_front();
+    }
+
+    let threshold = self.cfg.crash_loop_threshold;
+    if threshold > 0 && self.exits.len() as u32 >= threshold {
+      return Decision::Stop(format!(
+        "crash loop: {} exits within {:?}",
+        self.exits.len(),
+        window
+      ));
+    }
+    if self.restarts >= self.cfg.max_restarts {
+      return Decision::Stop(format!(
+        "restart limit of {} reached",
+        self.cfg.max_restarts
+      ));
+    }
+
+    let delay = self
+      .cfg
+      .backoff_secs
+      .saturating_mul(1 << self.restarts.min(16))
+      .min(self.cfg.max_backoff_secs);
+    self.restarts += 1;
+    Decision::Restart(Duration::from_secs(delay))
+  }
+}
@@ -0,0 +1,138 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use reqwest::Client;
+
+/// Endpoints of a product on the patch server that list builds, in the
+/// order they are searched. `bgdl` lists builds Battle.net downloads ahead
+/// of a patch.
+const ENDPOINTS: [&str; 2] = ["bgdl", "versions"];
+
+/// Regions that have their own patch server. Others, such as `cn`, are
+/// looked up on the `us` one, which lists every region.
+const PATCH_REGIONS: [&str; 4] = ["us", "eu", "kr", "tw"];
+
+/// How long a request to the patch server may take
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Looks up the version name (e.g. `11.0.5.57388`) of a build config on
+/// the patch server. `product` is the product column of `.build.info`.
+pub async fn version_for_build_config(
+  region: &str,
+  product: &str,
+  build_config: &str,
+) -> Result<Option<String>> {
+  let client = Client::builder().timeout(TIMEOUT).build()?;
+  for endpoint in ENDPOINTS {
+    let url = format!(
+      "http://{}.patch.battle.net:1119/{}/{}",
+      patch_region(region),
+      product,
+      endpoint
+    );
+    let body = client
+      .get(&url)
+      .send()
+      .await?
+      .error_for_status()?
+      .text()
+      .await?;
+
+    if let Some(version) = find_version(&body, build_config) {
+      return Ok(Some(version));
+    }
+  }
+
+  Ok(None)
+}
+
+/// The region of the patch server to ask about builds of `region`
+fn patch_region(region: &str) -> String {
+  let region = region.to_lowercase();
+  if PATCH_REGIONS.contains(&region.as_str()) {
+    region
+  } else {
+    "us".to_string()
+  }
+}
+
+/// Finds the version name of a build config in a patch server listing. The
+/// listing has a `Name!TYPE:size|...` header followed by `|` separated rows,
+/// with `##` comments in between.
+fn find_version(listing: &str, build_config: &str) -> Option<String> {
+  let mut lines = listing
+    .lines()
+    .filter(|line| !line.is_empty() && !line.starts_with("##"));
+
+  let header: Vec<&str> = lines
+    .next()?
+    .split('|')
+    .map(|column| column.split('!').next().unwrap_or(column))
+    .collect();
+  let column = |name: &str| header.iter().position(|h| h.eq_ignore_ascii_case(name));
+  let build_config_column = column("BuildConfig")?;
+  let version_column = column("VersionsName")?;
+
+  lines
+    .map(|line| line.split('|').collect::<Vec<_>>())
+    .find(|row| {
+      row
+        .get(build_config_column)
+        .is_some_and(|c| c.eq_ignore_ascii_case(build_config))
+    })
+    .and_then(|row| row.get(version_column).map(|v| v.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const VERSIONS: &str = "\
+Region!STRING:0|BuildConfig!HEX:16|CDNConfig!HEX:16|KeyRing!HEX:16|BuildId!DEC:4|VersionsName!String:0|ProductConfig!HEX:16
+## seqn = 2871345
+us|5a0e4a8b2c1f4d3e9b7a6c5d4e3f2a1b|8c7b6a5d4e3f2a1b0c9d8e7f6a5b4c3d||57388|11.0.5.57388|53020d32e1a25648c8e1eafd5771935f
+eu|5a0e4a8b2c1f4d3e9b7a6c5d4e3f2a1b|8c7b6a5d4e3f2a1b0c9d8e7f6a5b4c3d||57388|11.0.5.57388|53020d32e1a25648c8e1eafd5771935f
+## a comment between rows
+kr|0f1e2d3c4b5a69788796a5b4c3d2e1f0|8c7b6a5d4e3f2a1b0c9d8e7f6a5b4c3d||57689|11.0.7.57689|53020d32e1a25648c8e1eafd5771935f
+";
+
+  #[test]
+  fn finds_version_of_build_config() {
+    assert_eq!(
+      find_version(VERSIONS, "0f1e2d3c4b5a69788796a5b4c3d2e1f0"),
+      Some("11.0.7.57689".to_string())
+    );
+    assert_eq!(
+      find_version(VERSIONS, "5A0E4A8B2C1F4D3E9B7A6C5D4E3F2A1B"),
+      Some("11.0.5.57388".to_string())
+    );
+  }
+
+  #[test]
+  fn unknown_build_config_has_no_version() {
+    assert_eq!(
+      find_version(VERSIONS, "ffffffffffffffffffffffffffffffff"),
+      None
+    );
+  }
+
+  #[test]
+  fn listing_without_version_column_has_no_version() {
+    let listing = "\
+Region!STRING:0|BuildConfig!HEX:16|BuildId!DEC:4
+us|5a0e4a8b2c1f4d3e9b7a6c5d4e3f2a1b|57388
+";
+    assert_eq!(
+      find_version(listing, "5a0e4a8b2c1f4d3e9b7a6c5d4e3f2a1b"),
+      None
+    );
+    assert_eq!(find_version("", "5a0e4a8b2c1f4d3e9b7a6c5d4e3f2a1b"), None);
+  }
+
+  #[test]
+  fn unknown_regions_use_the_us_patch_server() {
+    assert_eq!(patch_region("EU"), "eu");
+    assert_eq!(patch_region("cn"), "us");
+    assert_eq!(patch_region("xx"), "us");
+  }
+}
@@ -0,0 +1,30 @@
+use std::collections::HashMap;
+
+/// Replaces `{name}` placeholders with values from `vars`. Placeholders
+/// without a value are left as they are.
+pub fn render(template: &str, vars: &HashMap<String, String>) -> String {
+  let mut out = String::with_capacity(template.len());
+  let mut rest = template;
+
+  while let Some(start) = rest.find('{') {
+    out.push_str(&rest[..start]);
+    rest = &rest[start..];
+
+    let value = rest
+      .find('}')
+      .and_then(|end| vars.get(&rest[1..end]).map(|value| (end, value)));
+    match value {
+      Some((end, value)) => {
+        out.push_str(value);
+        rest = &rest[end + 1..];
+      },
+      None => {
+        out.push('{');
+        rest = &rest[1..];
+      },
+    }
+  }
+
+  out.push_str(rest);
+  out
+}
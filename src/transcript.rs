@@ -0,0 +1,111 @@
+use std::fs;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+use anyhow::Context;
+use anyhow::Result;
+use anyhow::anyhow;
+use time::OffsetDateTime;
+use time::UtcOffset;
+use time::macros::format_description;
+
+/// Where this run's transcripts go
+struct Session {
+  dir: PathBuf,
+  run_id: String,
+  offset: UtcOffset,
+}
+
+static SESSION: OnceLock<Session> = OnceLock::new();
+
+/// Numbers transcripts in the order they were started
+static NEXT: AtomicUsize = AtomicUsize::new(1);
+
+/// Starts a run with a new id. Transcripts are written to `dir` and named
+/// after the run id.
+pub fn init(dir: PathBuf, offset: UtcOffset) {
+  let now = OffsetDateTime::now_utc().to_offset(offset);
+  let format = format_description!("[year][month][day]-[hour][minute][second]");
+  let run_id = format!(
+    "{}-{}",
+    now.format(format).unwrap_or_default(),
+    std::process::id()
+  );
+
+  let _ = SESSION.set(Session {
+    dir,
+    run_id,
+    offset,
+  });
+}
+
+/// The id of this run, once `init` was called
+pub fn run_id() -> Option<&'static str> {
+  SESSION.get().map(|s| s.run_id.as_str())
+}
+
+/// The plain text output of a single child process, one timestamped line at a
+/// time
+pub struct Transcript {
+  file: File,
+  path: PathBuf,
+  offset: UtcOffset,
+}
+
+impl Transcript {
+  /// Creates `<run id>-<number>-<name>.log` in the transcript directory
+  pub fn create(name: &str) -> Result<Self> {
+    let session = SESSION
+      .get()
+      .ok_or_else(|| anyhow!("transcripts are not initialized"))?;
+
+    fs::create_dir_all(&session.dir).context("Failed to create transcript directory")?;
+
+    let name: String = name
+      .chars()
+      .map(|c| {
+        if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+          c
+        } else {
+          '_'
+        }
+      })
+      .collect();
+    let number = NEXT.fetch_add(1, Ordering::SeqCst);
+    let path = session
+      .dir
+      .join(format!("{}-{:02}-{}.log", session.run_id, number, name));
+    let file = File::create(&path).context("Failed to create transcript")?;
+
+    Ok(Self {
+      file,
+      path,
+      offset: session.offset,
+    })
+  }
+
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  /// Gives up the file, for a child that writes its output there itself
+  pub fn into_file(self) -> File {
+    self.file
+  }
+
+  pub fn write_line(&mut self, text: &str) {
+    let now = OffsetDateTime::now_utc().to_offset(self.offset);
+    let format = format_description!("[hour]:[minute]:[second].[subsecond digits:3]");
+    let _ = writeln!(
+      self.file,
+      "[{}] {}",
+      now.format(format).unwrap_or_default(),
+      text
+    );
+  }
+}
@@ -0,0 +1,116 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+
+use serde::Deserialize;
+
+/// How to run Windows programs on other platforms
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct WineConfig {
+  /// The Wine prefix holding Battle.net and WoW. Exported as `WINEPREFIX`.
+  pub prefix: Option<String>,
+  /// The program used to run Windows executables, e.g. "wine" or "wine64"
+  pub command: String,
+  /// Arguments passed to `command` before the executable
+  pub args: Vec<String>,
+}
+
+impl Default for WineConfig {
+  fn default() -> Self {
+    Self {
+      prefix: None,
+      command: "wine".to_string(),
+      args: vec![],
+    }
+  }
+}
+
+impl WineConfig {
+  /// The configured prefix with a leading `~` expanded
+  pub fn prefix(&self) -> Option<PathBuf> {
+    self.prefix.as_deref().map(expand_home)
+  }
+}
+
+fn expand_home(path: &str) -> PathBuf {
+  match (path.strip_prefix("~/"), dirs::home_dir()) {
+    (Some(rest), Some(home)) => home.join(rest),
+    _ => PathBuf::from(path),
+  }
+}
+
+/// Maps a Windows path such as `C:/Program Files (x86)/World of Warcraft`
+/// to its location inside `prefix` through the prefix's `dosdevices` links
+pub fn to_host_path(prefix: &Path, windows_path: &str) -> PathBuf {
+  let normalized = windows_path.replace('\\', "/");
+  let (drive, rest) = match normalized.split_once(':') {
+    Some((drive, rest)) if drive.len() == 1 => (drive.to_lowercase(), rest),
+    _ => ("c".to_string(), normalized.as_str()),
+  };
+
+  let mut path = prefix.join("dosdevices").join(format!("{}:", drive));
+  if !path.exists() && drive == "c" {
+    path = prefix.join("drive_c");
+  }
+
+  path.join(rest.trim_start_matches('/'))
+}
+
+/// Builds a command that runs the Windows executable `program` through Wine
+/// in `prefix`, or Wine's default prefix when there is none
+pub fn command(cfg: &WineConfig, prefix: Option<&Path>, program: &Path) -> Command {
+  let mut cmd = Command::new(&cfg.command);
+  cmd.args(&cfg.args);
+  cmd.arg(program);
+  if let Some(prefix) = prefix {
+    cmd.env("WINEPREFIX", prefix);
+  }
+  cmd
+}
+
+/// Returns the Wine prefix `path` is inside of, if any
+pub fn prefix_of(path: &Path) -> Option<PathBuf> {
+  path
+    .ancestors()
+    .skip(1)
+    .find(|dir| dir.join("drive_c").is_dir())
+    .map(Path::to_path_buf)
+}
+
+/// Wine and Proton prefixes found in the usual places
+pub fn discover_prefixes() -> Vec<PathBuf> {
+  let mut prefixes: Vec<PathBuf> = env::var_os("WINEPREFIX")
+    .map(PathBuf::from)
+    .into_iter()
+    .collect();
+
+  if let Some(home) = dirs::home_dir() {
+    prefixes.push(home.join(".wine"));
+
+    // Lutris installs each game into its own prefix under ~/Games
+    prefixes.extend(subdirs(&home.join("Games")));
+
+    for steam in [".steam/steam", ".local/share/Steam"] {
+      let compatdata = home.join(steam).join("steamapps").join("compatdata");
+      prefixes.extend(subdirs(&compatdata).into_iter().map(|p| p.join("pfx")));
+    }
+  }
+
+  prefixes.retain(|p| p.join("drive_c").is_dir());
+  prefixes
+}
+
+fn subdirs(path: &Path) -> Vec<PathBuf> {
+  fs::read_dir(path)
+    .map(|entries| {
+      entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_dir())
+        .collect()
+    })
+    .unwrap_or_default()
+}